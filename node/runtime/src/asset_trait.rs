@@ -0,0 +1,33 @@
+use support::dispatch::Result;
+use codec::Codec;
+use sr_primitives::traits::{Member, SimpleArithmetic};
+
+/// Something that holds balances of several fungible assets, such as the native
+/// currency alongside assets issued on chain.
+pub trait MultiAsset<AccountId> {
+    /// Identifies one asset.
+    type AssetId: Member + Codec + Copy + Default;
+
+    /// A balance of any asset.
+    type Balance: SimpleArithmetic + Member + Codec + Copy + Default;
+
+    /// Whether the asset has been issued.
+    fn exists(asset: Self::AssetId) -> bool;
+
+    /// The balance of an asset that `who` can spend or reserve.
+    fn free_balance(asset: Self::AssetId, who: &AccountId) -> Self::Balance;
+
+    /// Move free balance of an asset from one account to another.
+    fn transfer(asset: Self::AssetId, from: &AccountId, to: &AccountId, amount: Self::Balance)
+      -> Result;
+
+    /// Set aside some of an account's free balance so that it can't be spent.
+    fn reserve(asset: Self::AssetId, who: &AccountId, amount: Self::Balance) -> Result;
+
+    /// Return up to `amount` of an account's reserved balance to its free balance.
+    fn unreserve(asset: Self::AssetId, who: &AccountId, amount: Self::Balance);
+
+    /// Move reserved balance of an asset from one account into another's free balance.
+    fn repatriate_reserved(asset: Self::AssetId, from: &AccountId, to: &AccountId, amount: Self::Balance)
+      -> Result;
+}
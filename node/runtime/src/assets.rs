@@ -0,0 +1,292 @@
+/// A minimal module for issuing fungible assets on chain. Together with the
+/// native currency, which is always asset 0, these assets are exposed to other
+/// modules through the `MultiAsset` trait, so that e.g. marketplace listings
+/// can be priced in something other than the native token.
+
+use support::{ensure, decl_module, decl_storage, decl_event, StorageValue, StorageMap, StorageDoubleMap, dispatch::Result};
+use support::traits::{Currency, ReservableCurrency};
+use sr_primitives::traits::{Zero, CheckedSub};
+use system::ensure_signed;
+
+use crate::asset_trait::MultiAsset;
+
+/// The module's configuration trait.
+pub trait Trait: system::Trait {
+    /// The native currency, available as asset 0.
+    type Currency: ReservableCurrency<Self::AccountId>;
+
+    /// The overarching event type.
+    type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+}
+
+pub type AssetId = u32;
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+
+/// The id under which the native currency is available.
+pub const NATIVE_ASSET_ID: AssetId = 0;
+
+// This module's storage items.
+decl_storage! {
+    trait Store for Module<T: Trait> as Assets {
+        // The most recently issued asset. Asset 0 is the native currency, so issued assets start at 1.
+        LastAssetId get(last_asset_id): AssetId;
+        TotalSupply get(total_supply): map AssetId => BalanceOf<T>;
+        Balances get(balance): double_map AssetId, blake2_256(T::AccountId) => BalanceOf<T>;
+        Reserved get(reserved): double_map AssetId, blake2_256(T::AccountId) => BalanceOf<T>;
+    }
+}
+
+decl_module! {
+    /// The module declaration.
+    pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+        // Initializing events
+        // this is needed only if you are using events in your module
+        fn deposit_event() = default;
+
+        /// Issue a new asset. The whole supply is credited to the issuer.
+        pub fn issue(origin, total: BalanceOf<T>) -> Result {
+            let issuer = ensure_signed(origin)?;
+
+            let asset = LastAssetId::get().checked_add(1).ok_or("No more asset ids available")?;
+            LastAssetId::put(asset);
+            <TotalSupply<T>>::insert(asset, total);
+            <Balances<T>>::insert(asset, &issuer, total);
+
+            Self::deposit_event(RawEvent::Issued(asset, issuer, total));
+            Ok(())
+        }
+
+        /// Transfer some of an issued asset to another account.
+        pub fn transfer(origin, asset: AssetId, dest: T::AccountId, amount: BalanceOf<T>) -> Result {
+            let sender = ensure_signed(origin)?;
+            ensure!(asset != NATIVE_ASSET_ID, "Use the balances module to transfer the native currency");
+
+            <Self as MultiAsset<T::AccountId>>::transfer(asset, &sender, &dest, amount)?;
+
+            Self::deposit_event(RawEvent::Transferred(asset, sender, dest, amount));
+            Ok(())
+        }
+    }
+}
+
+// Implement the multi-asset trait
+impl<T: Trait> MultiAsset<T::AccountId> for Module<T> {
+    type AssetId = AssetId;
+    type Balance = BalanceOf<T>;
+
+    fn exists(asset: AssetId) -> bool {
+        asset <= LastAssetId::get()
+    }
+
+    fn free_balance(asset: AssetId, who: &T::AccountId) -> BalanceOf<T> {
+        if asset == NATIVE_ASSET_ID {
+            T::Currency::free_balance(who)
+        } else {
+            <Balances<T>>::get(asset, who)
+        }
+    }
+
+    fn transfer(asset: AssetId, from: &T::AccountId, to: &T::AccountId, amount: BalanceOf<T>) -> Result {
+        if asset == NATIVE_ASSET_ID {
+            return T::Currency::transfer(from, to, amount);
+        }
+        ensure!(Self::exists(asset), "No such asset");
+
+        let from_balance = <Balances<T>>::get(asset, from).checked_sub(&amount)
+            .ok_or("Insufficient asset balance")?;
+        <Balances<T>>::insert(asset, from, from_balance);
+        <Balances<T>>::mutate(asset, to, |b| *b += amount);
+        Ok(())
+    }
+
+    fn reserve(asset: AssetId, who: &T::AccountId, amount: BalanceOf<T>) -> Result {
+        if asset == NATIVE_ASSET_ID {
+            return T::Currency::reserve(who, amount);
+        }
+        ensure!(Self::exists(asset), "No such asset");
+
+        let free = <Balances<T>>::get(asset, who).checked_sub(&amount)
+            .ok_or("Insufficient asset balance")?;
+        <Balances<T>>::insert(asset, who, free);
+        <Reserved<T>>::mutate(asset, who, |r| *r += amount);
+        Ok(())
+    }
+
+    fn unreserve(asset: AssetId, who: &T::AccountId, amount: BalanceOf<T>) {
+        if asset == NATIVE_ASSET_ID {
+            T::Currency::unreserve(who, amount);
+            return;
+        }
+
+        let reserved = <Reserved<T>>::get(asset, who);
+        let actual = if amount < reserved { amount } else { reserved };
+        <Reserved<T>>::insert(asset, who, reserved - actual);
+        <Balances<T>>::mutate(asset, who, |b| *b += actual);
+    }
+
+    fn repatriate_reserved(asset: AssetId, from: &T::AccountId, to: &T::AccountId, amount: BalanceOf<T>) -> Result {
+        if asset == NATIVE_ASSET_ID {
+            // Check first, as a partial repatriation would still move funds
+            ensure!(T::Currency::reserved_balance(from) >= amount, "Insufficient reserved balance");
+            let remaining = T::Currency::repatriate_reserved(from, to, amount)?;
+            ensure!(remaining.is_zero(), "Insufficient reserved balance");
+            return Ok(());
+        }
+
+        let reserved = <Reserved<T>>::get(asset, from).checked_sub(&amount)
+            .ok_or("Insufficient reserved balance")?;
+        <Reserved<T>>::insert(asset, from, reserved);
+        <Balances<T>>::mutate(asset, to, |b| *b += amount);
+        Ok(())
+    }
+}
+
+decl_event!(
+    pub enum Event<T> where
+        AccountId = <T as system::Trait>::AccountId,
+        Balance = BalanceOf<T>
+    {
+        // Asset, issuer, total supply
+        Issued(AssetId, AccountId, Balance),
+        // Asset, from, to, amount
+        Transferred(AssetId, AccountId, AccountId, Balance),
+    }
+);
+
+/// tests for this module
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use runtime_io::with_externalities;
+    use primitives::{H256, Blake2Hasher};
+    use support::{impl_outer_origin, assert_ok, assert_noop, parameter_types};
+    use sr_primitives::{
+        Perbill,
+        traits::{BlakeTwo256, IdentityLookup, ConvertInto},
+        testing::Header,
+    };
+
+    impl_outer_origin! {
+        pub enum Origin for Test {}
+    }
+
+    // For testing the module, we construct most of a mock runtime. This means
+    // first constructing a configuration type (`Test`) which `impl`s each of the
+    // configuration traits of modules we want to use.
+    #[derive(Clone, Eq, PartialEq)]
+    pub struct Test;
+    parameter_types! {
+        pub const BlockHashCount: u64 = 250;
+        pub const MaximumBlockWeight: u32 = 1024;
+        pub const MaximumBlockLength: u32 = 2 * 1024;
+        pub const AvailableBlockRatio: Perbill = Perbill::one();
+    }
+    impl system::Trait for Test {
+        type Origin = Origin;
+        type Call = ();
+        type Index = u64;
+        type BlockNumber = u64;
+        type Hash = H256;
+        type Hashing = BlakeTwo256;
+        type AccountId = u64;
+        type Lookup = IdentityLookup<Self::AccountId>;
+        type Header = Header;
+        type WeightMultiplierUpdate = ();
+        type Event = ();
+        type BlockHashCount = BlockHashCount;
+        type MaximumBlockWeight = MaximumBlockWeight;
+        type MaximumBlockLength = MaximumBlockLength;
+        type AvailableBlockRatio = AvailableBlockRatio;
+        type Version = ();
+    }
+
+    parameter_types! {
+        pub const ExistentialDeposit: u64 = 0;
+        pub const TransferFee: u64 = 0;
+        pub const CreationFee: u64 = 0;
+        pub const TransactionBaseFee: u64 = 0;
+        pub const TransactionByteFee: u64 = 0;
+    }
+    impl balances::Trait for Test {
+        type Balance = u64;
+        type OnFreeBalanceZero = ();
+        type OnNewAccount = ();
+        type Event = ();
+        type TransactionPayment = ();
+        type DustRemoval = ();
+        type TransferPayment = ();
+        type ExistentialDeposit = ExistentialDeposit;
+        type TransferFee = TransferFee;
+        type CreationFee = CreationFee;
+        type TransactionBaseFee = TransactionBaseFee;
+        type TransactionByteFee = TransactionByteFee;
+        type WeightToFee = ConvertInto;
+    }
+
+    impl Trait for Test {
+        type Currency = balances::Module<Test>;
+        type Event = ();
+    }
+    type Assets = Module<Test>;
+
+    // This function basically just builds a genesis storage key/value store according to
+    // our desired mockup.
+    fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+        let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+        balances::GenesisConfig::<Test> {
+            balances: vec![(1, 100)],
+            vesting: vec![],
+        }.assimilate_storage(&mut t).unwrap();
+        t.into()
+    }
+
+    #[test]
+    fn issued_assets_can_be_transferred() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Assets::issue(Origin::signed(1), 50));
+            assert_eq!(Assets::last_asset_id(), 1);
+            assert_eq!(Assets::balance(1, 1), 50);
+
+            assert_ok!(Assets::transfer(Origin::signed(1), 1, 2, 20));
+            assert_eq!(Assets::balance(1, 1), 30);
+            assert_eq!(Assets::balance(1, 2), 20);
+            assert_noop!(Assets::transfer(Origin::signed(2), 1, 1, 21), "Insufficient asset balance");
+            assert_noop!(Assets::transfer(Origin::signed(1), 2, 2, 1), "No such asset");
+        });
+    }
+
+    #[test]
+    fn reserved_assets_can_be_repatriated() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Assets::issue(Origin::signed(1), 50));
+            assert_ok!(<Assets as MultiAsset<u64>>::reserve(1, &1, 20));
+            assert_eq!(<Assets as MultiAsset<u64>>::free_balance(1, &1), 30);
+
+            assert_ok!(<Assets as MultiAsset<u64>>::repatriate_reserved(1, &1, &2, 15));
+            assert_eq!(Assets::reserved(1, 1), 5);
+            assert_eq!(Assets::balance(1, 2), 15);
+
+            <Assets as MultiAsset<u64>>::unreserve(1, &1, 10);
+            assert_eq!(Assets::reserved(1, 1), 0);
+            assert_eq!(Assets::balance(1, 1), 35);
+        });
+    }
+
+    #[test]
+    fn native_currency_is_asset_zero() {
+        with_externalities(&mut new_test_ext(), || {
+            assert!(<Assets as MultiAsset<u64>>::exists(NATIVE_ASSET_ID));
+            assert_eq!(<Assets as MultiAsset<u64>>::free_balance(NATIVE_ASSET_ID, &1), 100);
+            assert_ok!(<Assets as MultiAsset<u64>>::reserve(NATIVE_ASSET_ID, &1, 40));
+            assert_eq!(balances::Module::<Test>::reserved_balance(1), 40);
+
+            // Nothing moves unless all of it can
+            assert_noop!(
+                <Assets as MultiAsset<u64>>::repatriate_reserved(NATIVE_ASSET_ID, &1, &2, 50),
+                "Insufficient reserved balance"
+            );
+            assert_eq!(balances::Module::<Test>::reserved_balance(1), 40);
+        });
+    }
+}
@@ -5,8 +5,9 @@
 /// The module itself only accumulates the totals of positive and
 /// negative ratings. Calculating the final score should be done offchain.
 
+use rstd::prelude::*;
 use support::{decl_module, decl_storage, decl_event, StorageMap, dispatch::Result};
-use crate::reputation_trait::{ Reputation, DefaultFeedback };
+use crate::reputation_trait::{ Reputation, ExportReputation, ContextualReputation, DefaultFeedback };
 
 /// The module's configuration trait.
 pub trait Trait: system::Trait {
@@ -18,7 +19,7 @@ type Score = (u32, u32);
 
 // This module's storage items.
 decl_storage! {
-	trait Store for Module<T: Trait> as SimpleFeedback {
+	trait Store for Module<T: Trait> as BetaFeedback {
 		Positives: map T::AccountId => u32;
 		Negatives: map T::AccountId => u32;
 	}
@@ -41,13 +42,10 @@ impl<T: Trait> Reputation<T::AccountId> for Module<T> {
     fn rate(rater: T::AccountId, ratee: T::AccountId, feedback: DefaultFeedback) -> Result {
 
         // Update the individual accumulators
+        let count = Self::rated_count(&ratee, &feedback)?;
         match feedback {
-            DefaultFeedback::Positive => {
-                <Positives<T>>::mutate(&ratee, |p| *p += 1);
-            },
-            DefaultFeedback::Negative => {
-                <Negatives<T>>::mutate(&ratee, |n| *n += 1);
-            },
+            DefaultFeedback::Positive => <Positives<T>>::insert(&ratee, count),
+            DefaultFeedback::Negative => <Negatives<T>>::insert(&ratee, count),
         };
 
         Self::deposit_event(RawEvent::Rated(rater, ratee, feedback));
@@ -58,8 +56,35 @@ impl<T: Trait> Reputation<T::AccountId> for Module<T> {
     fn reputation(who: T::AccountId) -> Self::Score {
         (<Positives<T>>::get(&who), <Negatives<T>>::get(&who))
     }
+
+    fn can_rate(_rater: &T::AccountId, ratee: &T::AccountId, feedback: &DefaultFeedback) -> Result {
+        Self::rated_count(ratee, feedback).map(|_| ())
+    }
+}
+
+impl<T: Trait> Module<T> {
+    /// The ratee's total of the given kind of feedback once it is counted.
+    fn rated_count(ratee: &T::AccountId, feedback: &DefaultFeedback) -> rstd::result::Result<u32, &'static str> {
+        match feedback {
+            DefaultFeedback::Positive => <Positives<T>>::get(ratee),
+            DefaultFeedback::Negative => <Negatives<T>>::get(ratee),
+        }.checked_add(1).ok_or("Too many ratings")
+    }
 }
 
+impl<T: Trait> ExportReputation<T::AccountId> for Module<T> {
+    fn export(who: &T::AccountId, start: u32, count: u32) -> Vec<DefaultFeedback> {
+        // All positive feedback, then all negative
+        let positives = <Positives<T>>::get(who) as u64;
+        let end = (positives + <Negatives<T>>::get(who) as u64).min(start as u64 + count as u64);
+        (start as u64..end)
+            .map(|i| if i < positives { DefaultFeedback::Positive } else { DefaultFeedback::Negative })
+            .collect()
+    }
+}
+
+impl<T: Trait, Context> ContextualReputation<T::AccountId, Context> for Module<T> {}
+
 decl_event!(
 	pub enum Event<T> where AccountId = <T as system::Trait>::AccountId {
 		// User just submitted a rating
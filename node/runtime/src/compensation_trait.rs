@@ -0,0 +1,27 @@
+use crate::marketplace::ListingId;
+
+/// Something that knows which buyers were awarded compensation their seller
+/// couldn't pay, so that it can be made up from elsewhere, e.g. an insurance pool.
+pub trait CompensationShortfall<AccountId, AssetId, Balance> {
+    /// The buyer of a listing, the asset they were to be compensated in, and how
+    /// much of it went unpaid. `None` unless a dispute was decided for the buyer
+    /// and the seller's collateral fell short.
+    fn shortfall(listing_id: ListingId) -> Option<(AccountId, AssetId, Balance)>;
+
+    /// Record that `amount` of a listing's shortfall has been made up.
+    fn reduce(listing_id: ListingId, amount: Balance);
+
+    /// Forget a listing's shortfall, once all of it has been made up.
+    fn settle(listing_id: ListingId);
+}
+
+/// No buyer is ever owed anything.
+impl<AccountId, AssetId, Balance> CompensationShortfall<AccountId, AssetId, Balance> for () {
+    fn shortfall(_listing_id: ListingId) -> Option<(AccountId, AssetId, Balance)> {
+        None
+    }
+
+    fn reduce(_listing_id: ListingId, _amount: Balance) {}
+
+    fn settle(_listing_id: ListingId) {}
+}
@@ -0,0 +1,233 @@
+/// Run two reputation systems side by side. A tuple `(A, B)` of reputation
+/// systems is itself a reputation system: every rating is given to both, and
+/// the score is the pair of their scores. This allows e.g. Simple Feedback and
+/// Beta Feedback to be compared on the same live ratings, as long as both start
+/// out empty. Adding `B` next to an `A` that already holds reputation skews the
+/// comparison for every account rated before.
+///
+/// To blend the pair into a single score instead, wrap it in `Combined` with
+/// a `CombineScores` implementation.
+
+use rstd::prelude::*;
+use rstd::marker::PhantomData;
+use support::dispatch::Result;
+
+use crate::reputation_trait::{ Reputation, ExportReputation, ContextualReputation };
+
+// Both systems must accept the same kind of feedback.
+impl<AccountId, A, B> Reputation<AccountId> for (A, B) where
+    AccountId: Clone,
+    A: Reputation<AccountId>,
+    B: Reputation<AccountId, Feedback = A::Feedback>,
+{
+    type Score = (A::Score, B::Score);
+    type Feedback = A::Feedback;
+
+    /// Neither is rated unless both accept the rating, so that both see the same ratings.
+    fn rate(rater: AccountId, ratee: AccountId, feedback: A::Feedback) -> Result {
+        Self::can_rate(&rater, &ratee, &feedback)?;
+        A::rate(rater.clone(), ratee.clone(), feedback.clone())?;
+        B::rate(rater, ratee, feedback)
+    }
+
+    fn reputation(who: AccountId) -> Self::Score {
+        (A::reputation(who.clone()), B::reputation(who))
+    }
+
+    fn can_rate(rater: &AccountId, ratee: &AccountId, feedback: &A::Feedback) -> Result {
+        A::can_rate(rater, ratee, feedback)?;
+        B::can_rate(rater, ratee, feedback)
+    }
+}
+
+// Both systems have seen the same ratings, so `A` speaks for the pair. That only holds if
+// both were empty when the pair started being rated, e.g. when it was rebuilt by a replay.
+impl<AccountId, A, B> ExportReputation<AccountId> for (A, B) where
+    AccountId: Clone,
+    A: ExportReputation<AccountId>,
+    B: Reputation<AccountId, Feedback = A::Feedback>,
+{
+    fn export(who: &AccountId, start: u32, count: u32) -> Vec<A::Feedback> {
+        A::export(who, start, count)
+    }
+}
+
+impl<AccountId, Context, A, B> ContextualReputation<AccountId, Context> for (A, B) where
+    AccountId: Clone,
+    Context: Clone,
+    A: ContextualReputation<AccountId, Context>,
+    B: ContextualReputation<AccountId, Context> + Reputation<AccountId, Feedback = A::Feedback>,
+{
+    fn rate_in_context(rater: AccountId, ratee: AccountId, feedback: A::Feedback, context: Context) -> Result {
+        Self::can_rate(&rater, &ratee, &feedback)?;
+        A::rate_in_context(rater.clone(), ratee.clone(), feedback.clone(), context.clone())?;
+        B::rate_in_context(rater, ratee, feedback, context)
+    }
+}
+
+/// Turns the score of one reputation system into another kind of score,
+/// e.g. a weighted blend of the two scores of a pair.
+pub trait CombineScores<Score> {
+    type Combined;
+
+    fn combine(score: Score) -> Self::Combined;
+}
+
+/// A reputation system whose score is the score of `R`, combined by `C`.
+pub struct Combined<R, C>(PhantomData<(R, C)>);
+
+impl<AccountId, R, C> Reputation<AccountId> for Combined<R, C> where
+    R: Reputation<AccountId>,
+    C: CombineScores<R::Score>,
+{
+    type Score = C::Combined;
+    type Feedback = R::Feedback;
+
+    fn rate(rater: AccountId, ratee: AccountId, feedback: R::Feedback) -> Result {
+        R::rate(rater, ratee, feedback)
+    }
+
+    fn reputation(who: AccountId) -> Self::Score {
+        C::combine(R::reputation(who))
+    }
+
+    fn can_rate(rater: &AccountId, ratee: &AccountId, feedback: &R::Feedback) -> Result {
+        R::can_rate(rater, ratee, feedback)
+    }
+}
+
+impl<AccountId, R, C> ExportReputation<AccountId> for Combined<R, C> where
+    R: ExportReputation<AccountId>,
+    C: CombineScores<R::Score>,
+{
+    fn export(who: &AccountId, start: u32, count: u32) -> Vec<R::Feedback> {
+        R::export(who, start, count)
+    }
+}
+
+impl<AccountId, Context, R, C> ContextualReputation<AccountId, Context> for Combined<R, C> where
+    R: ContextualReputation<AccountId, Context>,
+    C: CombineScores<R::Score>,
+{
+    fn rate_in_context(rater: AccountId, ratee: AccountId, feedback: R::Feedback, context: Context) -> Result {
+        R::rate_in_context(rater, ratee, feedback, context)
+    }
+}
+
+/// tests for this module
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{simple_feedback, beta_feedback};
+    use crate::reputation_trait::DefaultFeedback;
+
+    use codec::Encode;
+    use runtime_io::with_externalities;
+    use primitives::{H256, Blake2Hasher};
+    use support::{impl_outer_origin, assert_ok, parameter_types};
+    use sr_primitives::{
+        Perbill,
+        traits::{BlakeTwo256, IdentityLookup},
+        testing::Header,
+    };
+
+    impl_outer_origin! {
+        pub enum Origin for Test {}
+    }
+
+    // For testing the module, we construct most of a mock runtime. This means
+    // first constructing a configuration type (`Test`) which `impl`s each of the
+    // configuration traits of modules we want to use.
+    #[derive(Clone, Eq, PartialEq)]
+    pub struct Test;
+    parameter_types! {
+        pub const BlockHashCount: u64 = 250;
+        pub const MaximumBlockWeight: u32 = 1024;
+        pub const MaximumBlockLength: u32 = 2 * 1024;
+        pub const AvailableBlockRatio: Perbill = Perbill::one();
+    }
+    impl system::Trait for Test {
+        type Origin = Origin;
+        type Call = ();
+        type Index = u64;
+        type BlockNumber = u64;
+        type Hash = H256;
+        type Hashing = BlakeTwo256;
+        type AccountId = u64;
+        type Lookup = IdentityLookup<Self::AccountId>;
+        type Header = Header;
+        type WeightMultiplierUpdate = ();
+        type Event = ();
+        type BlockHashCount = BlockHashCount;
+        type MaximumBlockWeight = MaximumBlockWeight;
+        type MaximumBlockLength = MaximumBlockLength;
+        type AvailableBlockRatio = AvailableBlockRatio;
+        type Version = ();
+    }
+
+    impl simple_feedback::Trait for Test {
+        type Event = ();
+    }
+    impl beta_feedback::Trait for Test {
+        type Event = ();
+    }
+    type SimpleFeedback = simple_feedback::Module<Test>;
+    type BetaFeedback = beta_feedback::Module<Test>;
+    type Both = (SimpleFeedback, BetaFeedback);
+
+    // Blends Simple Feedback's net score with Beta Feedback's (p + 1) / (p + n + 2),
+    // scaled to a percentage, weighing them equally.
+    pub struct Blend;
+    impl CombineScores<(i32, (u32, u32))> for Blend {
+        type Combined = i32;
+
+        fn combine((net, (p, n)): (i32, (u32, u32))) -> i32 {
+            let beta = (100 * (p as i32 + 1)) / (p as i32 + n as i32 + 2);
+            (net + beta) / 2
+        }
+    }
+
+    // This function basically just builds a genesis storage key/value store according to
+    // our desired mockup.
+    fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+        system::GenesisConfig::default().build_storage::<Test>().unwrap().into()
+    }
+
+    #[test]
+    fn pair_rates_both_systems() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Both::rate(1, 2, DefaultFeedback::Positive));
+            assert_ok!(Both::rate(3, 2, DefaultFeedback::Positive));
+            assert_ok!(Both::rate(4, 2, DefaultFeedback::Negative));
+
+            assert_eq!(Both::reputation(2), (1, (2, 1)));
+            assert_eq!(Both::export(&2, 0, 10), SimpleFeedback::export(&2, 0, 10));
+        });
+    }
+
+    #[test]
+    fn pair_is_rated_only_if_both_accept() {
+        with_externalities(&mut new_test_ext(), || {
+            // Beta Feedback can't count any more positive feedback for account 2
+            let mut raw_key = b"BetaFeedback Positives".to_vec();
+            2u64.encode_to(&mut raw_key);
+            support::storage::hashed::put(&runtime_io::blake2_256, &raw_key, &u32::max_value());
+
+            assert_eq!(Both::rate(1, 2, DefaultFeedback::Positive), Err("Too many ratings"));
+            assert_eq!(SimpleFeedback::reputation(2), 0);
+            assert_ok!(Both::rate(1, 2, DefaultFeedback::Negative));
+            assert_eq!(Both::reputation(2), (-1, (u32::max_value(), 1)));
+        });
+    }
+
+    #[test]
+    fn combined_scores_are_blended() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Combined::<Both, Blend>::rate(1, 2, DefaultFeedback::Positive));
+            assert_ok!(Combined::<Both, Blend>::rate(3, 2, DefaultFeedback::Positive));
+
+            // (2 + 75) / 2
+            assert_eq!(Combined::<Both, Blend>::reputation(2), 38);
+        });
+    }
+}
@@ -0,0 +1,282 @@
+/// An insurance pool for marketplace buyers. The pool is an account that the
+/// marketplace pays a fee from every sale into. When a buyer wins a dispute but
+/// the seller's collateral can't cover what they are owed, a governing origin can
+/// pay the buyer the shortfall from the pool instead. Payouts are capped per claim, and in total
+/// per asset in each payout period, so that the pool can't be drained at once.
+
+use support::{ensure, decl_module, decl_storage, decl_event, StorageMap, dispatch::Result};
+use support::traits::{Get, EnsureOrigin};
+use sr_primitives::{ModuleId, traits::{AccountIdConversion, Zero}};
+
+use crate::asset_trait::MultiAsset;
+use crate::compensation_trait::CompensationShortfall;
+use crate::marketplace::ListingId;
+
+/// The module's configuration trait.
+pub trait Trait: system::Trait {
+    /// The assets the pool holds and pays out in.
+    type Assets: MultiAsset<Self::AccountId>;
+
+    /// Where the compensation buyers were awarded but not paid is recorded.
+    type Shortfalls: CompensationShortfall<Self::AccountId, AssetIdOf<Self>, BalanceOf<Self>>;
+
+    /// The origin allowed to pay claims.
+    type ClaimOrigin: EnsureOrigin<Self::Origin>;
+
+    /// The most a single claim can pay out.
+    type MaxClaim: Get<BalanceOf<Self>>;
+
+    /// The most that can be paid out in each asset during one payout period.
+    type MaxPayoutPerPeriod: Get<BalanceOf<Self>>;
+
+    /// The length of a payout period.
+    type PayoutPeriod: Get<Self::BlockNumber>;
+
+    /// The overarching event type.
+    type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+}
+
+type AssetIdOf<T> = <<T as Trait>::Assets as MultiAsset<<T as system::Trait>::AccountId>>::AssetId;
+type BalanceOf<T> = <<T as Trait>::Assets as MultiAsset<<T as system::Trait>::AccountId>>::Balance;
+
+/// The pool's account is derived from this id.
+const MODULE_ID: ModuleId = ModuleId(*b"py/mktin");
+
+// This module's storage items.
+decl_storage! {
+    trait Store for Module<T: Trait> as Insurance {
+        // How much of each asset has been paid out, and in which payout period.
+        Payouts get(payouts): map AssetIdOf<T> => (T::BlockNumber, BalanceOf<T>);
+    }
+}
+
+decl_module! {
+    /// The module declaration.
+    pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+        // Initializing events
+        // this is needed only if you are using events in your module
+        fn deposit_event() = default;
+
+        /// The most a single claim can pay out.
+        const MaxClaim: BalanceOf<T> = T::MaxClaim::get();
+
+        /// The most that can be paid out per asset in each period.
+        const MaxPayoutPerPeriod: BalanceOf<T> = T::MaxPayoutPerPeriod::get();
+
+        /// The length of a payout period.
+        const PayoutPeriod: T::BlockNumber = T::PayoutPeriod::get();
+
+        /// Pay the buyer of a listing the compensation their seller's collateral didn't cover.
+        /// Pays less if the claim or period cap, or the pool's balance, doesn't allow all of it,
+        /// and the rest can be claimed later.
+        pub fn pay_claim(origin, listing_id: ListingId) -> Result {
+            T::ClaimOrigin::ensure_origin(origin)?;
+            let (buyer, asset, amount) = T::Shortfalls::shortfall(listing_id)
+                .ok_or("Nothing is owed to the buyer of this listing")?;
+
+            let period = <system::Module<T>>::block_number() / T::PayoutPeriod::get();
+            let (last_period, paid_before) = <Payouts<T>>::get(asset);
+            let paid_before = if last_period == period { paid_before } else { Zero::zero() };
+
+            let pool = Self::account_id();
+            let paid = amount
+                .min(T::MaxClaim::get())
+                .min(T::MaxPayoutPerPeriod::get().saturating_sub(paid_before))
+                .min(T::Assets::free_balance(asset, &pool));
+            ensure!(!paid.is_zero(), "Nothing can be paid out");
+
+            T::Assets::transfer(asset, &pool, &buyer, paid)?;
+            <Payouts<T>>::insert(asset, (period, paid_before + paid));
+            if paid == amount {
+                T::Shortfalls::settle(listing_id);
+            } else {
+                T::Shortfalls::reduce(listing_id, paid);
+            }
+
+            Self::deposit_event(RawEvent::ClaimPaid(buyer, listing_id, asset, paid));
+            Ok(())
+        }
+    }
+}
+
+impl<T: Trait> Module<T> {
+    /// The account holding the pool.
+    pub fn account_id() -> T::AccountId {
+        MODULE_ID.into_account()
+    }
+}
+
+/// Lets the pool be configured as where other modules send fees.
+impl<T: Trait> Get<T::AccountId> for Module<T> {
+    fn get() -> T::AccountId {
+        Self::account_id()
+    }
+}
+
+decl_event!(
+    pub enum Event<T> where
+        AccountId = <T as system::Trait>::AccountId,
+        AssetId = AssetIdOf<T>,
+        Balance = BalanceOf<T>
+    {
+        // Buyer, listing id, asset, amount paid
+        ClaimPaid(AccountId, ListingId, AssetId, Balance),
+    }
+);
+
+/// tests for this module
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets;
+
+    use std::cell::RefCell;
+    use runtime_io::with_externalities;
+    use primitives::{H256, Blake2Hasher};
+    use support::{impl_outer_origin, assert_ok, assert_noop, parameter_types};
+    use sr_primitives::{
+        Perbill,
+        traits::{BlakeTwo256, IdentityLookup, ConvertInto},
+        testing::Header,
+    };
+
+    impl_outer_origin! {
+        pub enum Origin for Test {}
+    }
+
+    // For testing the module, we construct most of a mock runtime. This means
+    // first constructing a configuration type (`Test`) which `impl`s each of the
+    // configuration traits of modules we want to use.
+    #[derive(Clone, Eq, PartialEq)]
+    pub struct Test;
+    parameter_types! {
+        pub const BlockHashCount: u64 = 250;
+        pub const MaximumBlockWeight: u32 = 1024;
+        pub const MaximumBlockLength: u32 = 2 * 1024;
+        pub const AvailableBlockRatio: Perbill = Perbill::one();
+    }
+    impl system::Trait for Test {
+        type Origin = Origin;
+        type Call = ();
+        type Index = u64;
+        type BlockNumber = u64;
+        type Hash = H256;
+        type Hashing = BlakeTwo256;
+        type AccountId = u64;
+        type Lookup = IdentityLookup<Self::AccountId>;
+        type Header = Header;
+        type WeightMultiplierUpdate = ();
+        type Event = ();
+        type BlockHashCount = BlockHashCount;
+        type MaximumBlockWeight = MaximumBlockWeight;
+        type MaximumBlockLength = MaximumBlockLength;
+        type AvailableBlockRatio = AvailableBlockRatio;
+        type Version = ();
+    }
+
+    parameter_types! {
+        pub const ExistentialDeposit: u64 = 0;
+        pub const TransferFee: u64 = 0;
+        pub const CreationFee: u64 = 0;
+        pub const TransactionBaseFee: u64 = 0;
+        pub const TransactionByteFee: u64 = 0;
+    }
+    impl balances::Trait for Test {
+        type Balance = u64;
+        type OnFreeBalanceZero = ();
+        type OnNewAccount = ();
+        type Event = ();
+        type TransactionPayment = ();
+        type DustRemoval = ();
+        type TransferPayment = ();
+        type ExistentialDeposit = ExistentialDeposit;
+        type TransferFee = TransferFee;
+        type CreationFee = CreationFee;
+        type TransactionBaseFee = TransactionBaseFee;
+        type TransactionByteFee = TransactionByteFee;
+        type WeightToFee = ConvertInto;
+    }
+
+    impl assets::Trait for Test {
+        type Currency = balances::Module<Test>;
+        type Event = ();
+    }
+
+    thread_local! {
+        // Listing n was bought by account n + 1, who is owed this much of the native currency.
+        static OWED: RefCell<Vec<u64>> = RefCell::new(vec![40, 30, 10]);
+    }
+
+    pub struct Shortfalls;
+    impl CompensationShortfall<u64, u32, u64> for Shortfalls {
+        fn shortfall(listing_id: ListingId) -> Option<(u64, u32, u64)> {
+            OWED.with(|o| o.borrow().get(listing_id as usize).cloned())
+                .filter(|amount| *amount > 0)
+                .map(|amount| (listing_id as u64 + 1, 0, amount))
+        }
+
+        fn reduce(listing_id: ListingId, amount: u64) {
+            OWED.with(|o| o.borrow_mut()[listing_id as usize] -= amount);
+        }
+
+        fn settle(listing_id: ListingId) {
+            OWED.with(|o| o.borrow_mut()[listing_id as usize] = 0);
+        }
+    }
+
+    parameter_types! {
+        pub const MaxClaim: u64 = 30;
+        pub const MaxPayoutPerPeriod: u64 = 50;
+        pub const PayoutPeriod: u64 = 10;
+    }
+    impl Trait for Test {
+        type Assets = assets::Module<Test>;
+        type Shortfalls = Shortfalls;
+        type ClaimOrigin = system::EnsureRoot<u64>;
+        type MaxClaim = MaxClaim;
+        type MaxPayoutPerPeriod = MaxPayoutPerPeriod;
+        type PayoutPeriod = PayoutPeriod;
+        type Event = ();
+    }
+    type Balances = balances::Module<Test>;
+    type Insurance = Module<Test>;
+
+    // This function basically just builds a genesis storage key/value store according to
+    // our desired mockup. The pool starts with 100 units.
+    fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+        let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+        balances::GenesisConfig::<Test> {
+            balances: vec![(Insurance::account_id(), 100)],
+            vesting: vec![],
+        }.assimilate_storage(&mut t).unwrap();
+        t.into()
+    }
+
+    #[test]
+    fn claims_are_capped() {
+        with_externalities(&mut new_test_ext(), || {
+            system::Module::<Test>::set_block_number(1);
+            assert_noop!(Insurance::pay_claim(Origin::signed(1), 0), "Invalid origin");
+            assert_noop!(Insurance::pay_claim(Origin::ROOT, 3), "Nothing is owed to the buyer of this listing");
+
+            // Capped per claim, and the rest can be claimed again
+            assert_ok!(Insurance::pay_claim(Origin::ROOT, 0));
+            assert_eq!(Balances::free_balance(1), 30);
+            assert_ok!(Insurance::pay_claim(Origin::ROOT, 0));
+            assert_eq!(Balances::free_balance(1), 40);
+            assert_noop!(Insurance::pay_claim(Origin::ROOT, 0), "Nothing is owed to the buyer of this listing");
+
+            // Only 10 is left for this period
+            assert_ok!(Insurance::pay_claim(Origin::ROOT, 1));
+            assert_eq!(Balances::free_balance(2), 10);
+            assert_noop!(Insurance::pay_claim(Origin::ROOT, 2), "Nothing can be paid out");
+
+            // The cap resets in the next period
+            system::Module::<Test>::set_block_number(10);
+            assert_ok!(Insurance::pay_claim(Origin::ROOT, 1));
+            assert_eq!(Balances::free_balance(2), 30);
+            assert_ok!(Insurance::pay_claim(Origin::ROOT, 2));
+            assert_eq!(Balances::free_balance(3), 10);
+        });
+    }
+}
@@ -67,6 +67,19 @@ mod marketplace;
 mod reputation_trait;
 mod simple_feedback;
 mod beta_feedback;
+mod reputation_log;
+mod composite_reputation;
+
+mod asset_trait;
+mod assets;
+
+mod verification_trait;
+mod verification;
+
+mod compensation_trait;
+mod insurance;
+
+mod migration;
 
 /// Opaque types. These are used by the CLI to instantiate machinery that don't need to know
 /// the specifics of the runtime. They can then be made to be agnostic over specific formats
@@ -101,8 +114,8 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("marketplace"),
 	impl_name: create_runtime_str!("marketplace"),
 	authoring_version: 3,
-	spec_version: 4,
-	impl_version: 4,
+	spec_version: 21,
+	impl_version: 21,
 	apis: RUNTIME_API_VERSIONS,
 };
 
@@ -256,9 +269,87 @@ impl sudo::Trait for Runtime {
 	type Proposal = Call;
 }
 
+parameter_types! {
+	pub const ListingDeposit: Balance = 1_000;
+	pub const SaleFee: Perbill = Perbill::from_percent(1);
+	pub const MaxTags: u32 = 8;
+	pub const MaxCartSize: u32 = 16;
+	pub const MaxMilestones: u32 = 16;
+	pub const MaxRentalPeriod: u32 = 90 * DAYS;
+	pub const MaxPaymentsPerBlock: u32 = 100;
+	pub const BlindReviewPeriod: BlockNumber = 3 * DAYS;
+	pub const MaxCommentLength: u32 = 256;
+	pub const ReturnWindow: BlockNumber = 14 * DAYS;
+	pub const CollateralRatio: Perbill = Perbill::from_percent(20);
+	pub const ReportBond: Balance = 100;
+	pub const ReportThreshold: u32 = 3;
+	pub const ReporterReward: Perbill = Perbill::from_percent(50);
+}
+
 impl marketplace::Trait for Runtime {
-	type ReputationSystem = SimpleFeedback;
-	//type ReputationSystem = BetaFeedback;
+	type ReputationSystem = ReputationLog;
+	type Balance = Balance;
+	type Currency = Balances;
+	type Assets = Assets;
+	type ListingDeposit = ListingDeposit;
+	type SaleFee = SaleFee;
+	type InsurancePool = Insurance;
+	type Verification = Verification;
+	type CategoryOrigin = system::EnsureRoot<AccountId>;
+	type ModeratorOrigin = system::EnsureRoot<AccountId>;
+	type ReportBond = ReportBond;
+	type ReportThreshold = ReportThreshold;
+	type ReporterReward = ReporterReward;
+	type ReturnWindow = ReturnWindow;
+	type CollateralRatio = CollateralRatio;
+	type MaxTags = MaxTags;
+	type MaxCartSize = MaxCartSize;
+	type MaxMilestones = MaxMilestones;
+	type MaxRentalPeriod = MaxRentalPeriod;
+	type MaxPaymentsPerBlock = MaxPaymentsPerBlock;
+	type BlindReviewPeriod = BlindReviewPeriod;
+	type MaxCommentLength = MaxCommentLength;
+	type Event = Event;
+}
+
+impl verification::Trait for Runtime {
+	type VerifierOrigin = system::EnsureRoot<AccountId>;
+	type Event = Event;
+}
+
+parameter_types! {
+	pub const MaxClaim: Balance = 10_000;
+	pub const MaxPayoutPerPeriod: Balance = 100_000;
+	pub const PayoutPeriod: BlockNumber = 7 * DAYS;
+}
+
+impl insurance::Trait for Runtime {
+	type Assets = Assets;
+	type Shortfalls = Marketplace;
+	type ClaimOrigin = system::EnsureRoot<AccountId>;
+	type MaxClaim = MaxClaim;
+	type MaxPayoutPerPeriod = MaxPayoutPerPeriod;
+	type PayoutPeriod = PayoutPeriod;
+	type Event = Event;
+}
+
+impl assets::Trait for Runtime {
+	type Currency = Balances;
+	type Event = Event;
+}
+
+parameter_types! {
+	pub const ReplayBatch: u32 = 100;
+}
+
+impl reputation_log::Trait for Runtime {
+	// Switching backends needs every rated account imported first, and then a replay into
+	// backends that hold no reputation yet. A pair is only comparable if both halves are replayed.
+	type Backend = SimpleFeedback;
+	//type Backend = BetaFeedback;
+	//type Backend = (SimpleFeedback, BetaFeedback);
+	type Context = marketplace::ListingId;
+	type ReplayBatch = ReplayBatch;
 	type Event = Event;
 }
 
@@ -266,9 +357,9 @@ impl simple_feedback::Trait for Runtime {
 	type Event = Event;
 }
 
-// impl beta_feedback::Trait for Runtime {
-// 	type Event = Event;
-// }
+impl beta_feedback::Trait for Runtime {
+	type Event = Event;
+}
 
 construct_runtime!(
 	pub enum Runtime where
@@ -283,9 +374,13 @@ construct_runtime!(
 		Indices: indices::{default, Config<T>},
 		Balances: balances,
 		Sudo: sudo,
-		Marketplace: marketplace::{Module, Call, Storage, Event<T>},
+		Marketplace: marketplace::{Module, Call, Storage, Event<T>, Config},
+		Assets: assets::{Module, Call, Storage, Event<T>},
+		Verification: verification::{Module, Call, Storage, Event<T>},
+		Insurance: insurance::{Module, Call, Storage, Event<T>},
+		ReputationLog: reputation_log::{Module, Call, Storage, Event<T>},
 		SimpleFeedback: simple_feedback::{Module, Storage, Event<T>},
-		//BetaFeedback: beta_feedback::{Module, Storage, Event<T>},
+		BetaFeedback: beta_feedback::{Module, Storage, Event<T>},
 	}
 );
 
@@ -414,6 +509,32 @@ impl_runtime_apis! {
 		}
 	}
 
+	impl marketplace::MarketplaceApi<Block, AccountId, assets::AssetId, Balance, BlockNumber, reputation_trait::DefaultFeedback> for Runtime {
+		fn listings_in_category(category: marketplace::CategoryId, start: u32, count: u32)
+			-> Vec<(marketplace::ListingId, marketplace::Listing<AccountId, assets::AssetId, Balance>, bool)>
+		{
+			Marketplace::listings_in_category(category, start, count)
+		}
+
+		fn listings_by_seller(seller: AccountId, start: u32, count: u32) -> Vec<marketplace::ListingId> {
+			Marketplace::listings_by_seller(seller, start, count)
+		}
+
+		fn listings_by_buyer(buyer: AccountId, start: u32, count: u32) -> Vec<marketplace::ListingId> {
+			Marketplace::listings_by_buyer(buyer, start, count)
+		}
+
+		fn trade_history(who: AccountId, start: u32, count: u32)
+			-> Vec<(marketplace::ListingId, marketplace::Sale<AccountId, assets::AssetId, Balance, BlockNumber, reputation_trait::DefaultFeedback>)>
+		{
+			Marketplace::trade_history(who, start, count)
+		}
+
+		fn is_bonded(seller: AccountId) -> bool {
+			Marketplace::is_bonded(&seller)
+		}
+	}
+
 	impl substrate_session::SessionKeys<Block> for Runtime {
 		fn generate_session_keys(seed: Option<Vec<u8>>) -> Vec<u8> {
 			let seed = seed.as_ref().map(|s| rstd::str::from_utf8(&s).expect("Seed is an utf8 string"));
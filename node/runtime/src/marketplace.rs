@@ -3,33 +3,248 @@
 /// minimal example of a system that requires a reputation system
 /// and demonstrates how to use various reputation systems
 
-use support::{ensure, decl_module, decl_storage, decl_event, StorageValue, StorageMap, dispatch::Result};
+use rstd::{prelude::*, result};
+use support::{ensure, decl_module, decl_storage, decl_event, StorageValue, StorageMap, StorageDoubleMap, dispatch::Result};
+use support::traits::{ReservableCurrency, Get, EnsureOrigin};
+use sr_primitives::{Perbill, traits::{Member, SimpleArithmetic, Saturating, CheckedAdd, CheckedMul, Zero, Hash}};
 use system::ensure_signed;
-use codec::{ Encode, Decode };
+use codec::{ Encode, Decode, Codec };
 
 // Use the Reputation trait
-use crate::reputation_trait::Reputation;
+use crate::reputation_trait::{ Reputation, ContextualReputation };
+// Use the MultiAsset trait
+use crate::asset_trait::MultiAsset;
+use crate::verification_trait::SellerVerification;
+use crate::compensation_trait::CompensationShortfall;
+use crate::migration::VersionedStorage;
 
 /// Marketplace configuration trait.
 pub trait Trait: system::Trait {
     // Notaion of reputation system
-    type ReputationSystem: Reputation<Self::AccountId>;
+    type ReputationSystem: ContextualReputation<Self::AccountId, ListingId>;
+
+    /// The type of prices and deposits.
+    type Balance: SimpleArithmetic + Member + Codec + Copy + Default;
+
+    /// The currency in which listing deposits are reserved.
+    type Currency: ReservableCurrency<Self::AccountId, Balance = Self::Balance>;
+
+    /// The assets listings may be priced in. A buyer's payment is held as reserved
+    /// balance of the listing's asset until the sale settles.
+    type Assets: MultiAsset<Self::AccountId, Balance = Self::Balance>;
+
+    /// The amount reserved from a seller for each listing they post. It is returned
+    /// when the listing is cancelled or settled, and slashed if the listing is removed.
+    type ListingDeposit: Get<Self::Balance>;
+
+    /// The share of each sale's price taken as a fee when the sale settles.
+    type SaleFee: Get<Perbill>;
+
+    /// The account sale fees are paid into, which insures buyers against sellers who
+    /// can't cover what they owe.
+    type InsurancePool: Get<Self::AccountId>;
+
+    /// Which sellers have had their identity verified.
+    type Verification: SellerVerification<Self::AccountId>;
+
+    /// The origin allowed to add and remove listing categories.
+    type CategoryOrigin: EnsureOrigin<Self::Origin>;
+
+    /// The origin allowed to remove listings and suspend accounts.
+    type ModeratorOrigin: EnsureOrigin<Self::Origin>;
+
+    /// The amount reserved from an account for each report it makes. Returned if the
+    /// report is upheld, and slashed if it is dismissed.
+    type ReportBond: Get<Self::Balance>;
+
+    /// How many reports hide a listing until a moderator has looked at it.
+    type ReportThreshold: Get<u32>;
+
+    /// The share of a seller's deposit paid to the reporters of a listing that is
+    /// removed because of their reports.
+    type ReporterReward: Get<Perbill>;
+
+    /// How much collateral, relative to the combined price of their listings on sale or
+    /// awaiting settlement in an asset, a seller must lock in that asset to count as bonded.
+    type CollateralRatio: Get<Perbill>;
+
+    /// How long after buying a listing the buyer may ask to return it.
+    type ReturnWindow: Get<Self::BlockNumber>;
+
+    /// The maximum number of tags a single listing may carry.
+    type MaxTags: Get<u32>;
+
+    /// The maximum number of listings that can be bought together, either with
+    /// `buy_many` or as one bundle.
+    type MaxCartSize: Get<u32>;
+
+    /// The maximum number of milestones a service listing may be split into.
+    type MaxMilestones: Get<u32>;
+
+    /// The maximum number of blocks a listing can be rented for at once.
+    type MaxRentalPeriod: Get<u32>;
+
+    /// The maximum number of subscription payments taken in one block. Payments
+    /// beyond it are carried over to the next block.
+    type MaxPaymentsPerBlock: Get<u32>;
+
+    /// How long the parties to a blind review have to commit, counted from the first
+    /// commitment, and then to reveal, counted from the second.
+    type BlindReviewPeriod: Get<Self::BlockNumber>;
+
+    /// The maximum length, in bytes, of a review comment or rebuttal.
+    type MaxCommentLength: Get<u32>;
 
     /// The overarching event type.
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
 }
 
-type ListingId = u32;
+pub type ListingId = u32;
+pub type CategoryId = u32;
+// Tags, like descriptions, are just numbers for now.
+pub type Tag = u32;
+pub type BundleId = u32;
+// Why a moderator removed a listing. Meanings are agreed off chain.
+pub type ReasonCode = u32;
 type FeedbackOf<T> = <<T as Trait>::ReputationSystem as Reputation<<T as system::Trait>::AccountId>>::Feedback;
+type AssetIdOf<T> = <<T as Trait>::Assets as MultiAsset<<T as system::Trait>::AccountId>>::AssetId;
+type ListingOf<T> = Listing<<T as system::Trait>::AccountId, AssetIdOf<T>, <T as Trait>::Balance>;
+type BundleOf<T> = Bundle<<T as system::Trait>::AccountId, <T as Trait>::Balance>;
+type SaleOf<T> = Sale<<T as system::Trait>::AccountId, AssetIdOf<T>, <T as Trait>::Balance, <T as system::Trait>::BlockNumber, FeedbackOf<T>>;
+type ReportOf<T> = Report<<T as system::Trait>::AccountId, <T as Trait>::Balance>;
+type ReviewOf<T> = Review<<T as system::Trait>::AccountId, FeedbackOf<T>>;
+type MilestoneOf<T> = Milestone<<T as Trait>::Balance>;
+type SubscriptionOf<T> = Subscription<<T as Trait>::Balance, <T as system::Trait>::BlockNumber>;
+type BlindReviewOf<T> = BlindReview<<T as system::Trait>::AccountId, <T as system::Trait>::Hash, FeedbackOf<T>, <T as system::Trait>::BlockNumber>;
 
 #[derive(Encode, Decode, Default, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "std", derive(Debug))]
-pub struct Listing <AccountId> {
+pub struct Listing <AccountId, AssetId, Balance> {
     seller: AccountId,
-    price: u32,
+    price: Balance,
+    // The asset the price is denominated in.
+    asset: AssetId,
     // Description should be string, but those are hard
     // And we're just testing.
     description: u32,
+    category: CategoryId,
+    tags: Vec<Tag>,
+    // Incremented every time the seller updates the listing.
+    version: u32,
+}
+
+/// Several of one seller's active listings offered together at a discount
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Bundle <AccountId, Balance> {
+    seller: AccountId,
+    listings: Vec<ListingId>,
+    // Price for the whole bundle. Always less than the members' combined price.
+    price: Balance,
+}
+
+/// The record of a settled sale, kept once the listing itself is gone
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Sale <AccountId, AssetId, Balance, BlockNumber, Feedback> {
+    seller: AccountId,
+    buyer: AccountId,
+    asset: AssetId,
+    // What the buyer actually paid, which is less than the listing price if bought in a bundle.
+    price: Balance,
+    settled: BlockNumber,
+    // Feedback each party gave the other. Blind reviews are filled in once revealed.
+    seller_feedback: Option<Feedback>,
+    buyer_feedback: Option<Feedback>,
+}
+
+/// A review of one party to a sale by the other, kept after the sale settles
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Review <AccountId, Feedback> {
+    reviewer: AccountId,
+    reviewee: AccountId,
+    feedback: Feedback,
+    // Free text, or e.g. the hash of content stored elsewhere.
+    comment: Vec<u8>,
+    // The reviewee's single response to the review.
+    rebuttal: Option<Vec<u8>>,
+}
+
+/// One account's complaint about a listing
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Report <AccountId, Balance> {
+    reporter: AccountId,
+    reason: ReasonCode,
+    // The bond actually reserved, so that changing `ReportBond` never releases more or less than was taken.
+    bond: Balance,
+}
+
+/// Reviews of a sale that neither party can see until both have committed to theirs.
+/// Kept after the listing settles, until the feedback has been revealed.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct BlindReview <AccountId, Hash, Feedback, BlockNumber> {
+    seller: AccountId,
+    buyer: AccountId,
+    // Hash of each party's feedback and a salt of their choosing.
+    seller_commitment: Option<Hash>,
+    buyer_commitment: Option<Hash>,
+    seller_feedback: Option<Feedback>,
+    buyer_feedback: Option<Feedback>,
+    // Set once both parties have committed, or one has and the other ran out of time.
+    revealing: bool,
+    // When the current phase ends.
+    deadline: BlockNumber,
+}
+
+/// One stage of a service listing, paid for separately once the buyer is happy with it
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Milestone <Balance> {
+    // This stage's share of the listing price.
+    amount: Balance,
+    // How much of the amount has been paid to the seller. The rest, if settled, was refunded.
+    paid: Balance,
+    state: MilestoneState,
+}
+
+/// Where a milestone of a service listing is up to
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum MilestoneState {
+    // Being worked on, or waiting for the buyer to approve.
+    Pending,
+    // The buyer approved the work and the seller was paid in full.
+    Approved,
+    // The buyer disputes the work, and a moderator has yet to decide.
+    Disputed,
+    // A moderator split the amount between the seller and the buyer.
+    Resolved,
+}
+
+/// One account's subscription to a listing
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Subscription <Balance, BlockNumber> {
+    // What each period costs, fixed when subscribing so the seller can't raise it.
+    price: Balance,
+    // How much more the subscriber has authorised to be taken from them.
+    allowance: Balance,
+    // When the next payment is due.
+    next_payment: BlockNumber,
+    // Set when a payment fails. No more payments are taken until the subscriber resumes.
+    paused: bool,
+}
+
+/// Layout of `Listing` at storage version 0, before categories, tags, versions, assets
+/// and deposits. Only used to migrate listings already in storage.
+#[derive(Decode)]
+struct BaselineListing <AccountId> {
+    seller: AccountId,
+    price: u32,
+    description: u32,
 }
 
 /// States a listing can be in
@@ -43,6 +258,19 @@ pub enum Status {
     NonExistant,
     // Listings are never explicitly marked NonExistant. It exists only to be default.
     // Once both reviews have come in, the sale is removed from storage, and an event emitted.
+    // Hidden from browsing and buying after too many reports, until a moderator resolves them.
+    Flagged,
+    // The buyer has asked to return the item, and the seller has yet to answer.
+    ReturnRequested,
+    // A service that has been bought, but still has milestones to approve or resolve.
+    // It becomes Sold, and can be reviewed, once they are all settled.
+    InProgress,
+    // A rental listing that has been rented, until the renter returns the item or
+    // the return block passes. It becomes Sold, and can be reviewed, once returned.
+    Rented,
+    // The rented item wasn't returned by the return block. The seller may still
+    // confirm its return, or claim the renter's deposit.
+    Overdue,
 }
 
 impl Default for Status {
@@ -56,9 +284,93 @@ impl Default for Status {
 decl_storage! {
     trait Store for Module<T: Trait> as Marketplace {
         NextId get(next_id): ListingId;
-        Listings get(listing): map ListingId => Option<Listing<T::AccountId>>;
+        Listings get(listing): map ListingId => Option<ListingOf<T>>;
         Buyers get(buyer): map ListingId => Option<T::AccountId>;
         Statuses get(status): map ListingId => Status;
+        // The deposit actually reserved for each listing, so that changing
+        // `ListingDeposit` never releases more or less than was taken.
+        Deposits get(deposit): map ListingId => T::Balance;
+        // The block each listing was bought in, while it awaits settlement.
+        SoldAt get(sold_at): map ListingId => Option<T::BlockNumber>;
+        // What the buyer of each sold listing paid. It stays reserved in the buyer's
+        // account until the sale settles, then goes to the seller.
+        Escrow get(escrow): map ListingId => T::Balance;
+        // The milestones of each service listing. The escrow of a service is paid out
+        // milestone by milestone, so only what is left of it remains in `Escrow`.
+        Milestones get(milestones): map ListingId => Vec<MilestoneOf<T>>;
+        // The deposit renters of each rental listing must put down. The listing's price is
+        // its rent per block. While rented, the deposit stays reserved in the renter's account.
+        RentalDeposits get(rental_deposit): map ListingId => Option<T::Balance>;
+        // The block by which each rented item must be returned.
+        ReturnDue get(return_due): map ListingId => Option<T::BlockNumber>;
+        // Rentals due back at each block. Entries for items already returned are skipped.
+        RentalsDue: map T::BlockNumber => Vec<ListingId>;
+        // How often subscribers to each subscription listing pay its price. Subscription
+        // listings stay active, so any number of accounts can subscribe.
+        SubscriptionPeriods get(subscription_period): map ListingId => Option<T::BlockNumber>;
+        Subscriptions get(subscription): double_map ListingId, blake2_256(T::AccountId) => Option<SubscriptionOf<T>>;
+        // Subscription payments due at each block. Entries for subscriptions that have
+        // since been paused, resumed or cancelled are skipped.
+        PaymentsDue: map T::BlockNumber => Vec<(ListingId, T::AccountId)>;
+
+        // Registry of categories a listing may be posted in. The value is the
+        // category's name, which is just a number like listing descriptions.
+        Categories get(category) config(): map CategoryId => Option<u32>;
+        // Active listings in each category, stored as a dense array so clients can page through them.
+        CategoryListings get(category_listing): double_map CategoryId, blake2_256(u32) => ListingId;
+        CategorySizes get(category_size): map CategoryId => u32;
+        // Where each active listing sits in its category's array.
+        CategoryPositions: map ListingId => Option<(CategoryId, u32)>;
+        // How many listings of each category are in storage, whether for sale or not.
+        // Sold listings can go back on sale, so a category can't be removed while it has any.
+        CategoryListingCounts get(category_listing_count): map CategoryId => u32;
+        // Categories only verified sellers may post in.
+        VerifiedOnly get(verified_only): map CategoryId => bool;
+
+        // Listings each account is selling or has sold, and listings each account has bought,
+        // stored as dense arrays like the category index. Entries are removed once the
+        // listing leaves storage.
+        SellerListings get(seller_listing): double_map T::AccountId, blake2_256(u32) => ListingId;
+        SellerListingCounts get(seller_listing_count): map T::AccountId => u32;
+        SellerPositions: map ListingId => u32;
+        BuyerListings get(buyer_listing): double_map T::AccountId, blake2_256(u32) => ListingId;
+        BuyerListingCounts get(buyer_listing_count): map T::AccountId => u32;
+        BuyerPositions: map ListingId => u32;
+
+        NextBundleId get(next_bundle_id): BundleId;
+        Bundles get(bundle): map BundleId => Option<BundleOf<T>>;
+        // The bundle, if any, that each listing belongs to. A listing is in at most one bundle.
+        ListingBundles get(listing_bundle): map ListingId => Option<BundleId>;
+
+        // Every settled sale, and the sales each account took part in as buyer or seller,
+        // stored as a dense array like the indexes above but never removed from.
+        Sales get(sale): map ListingId => Option<SaleOf<T>>;
+        Trades get(trade): double_map T::AccountId, blake2_256(u32) => ListingId;
+        TradeCounts get(trade_count): map T::AccountId => u32;
+
+        // Reports against each active or flagged listing.
+        Reports get(reports): map ListingId => Vec<ReportOf<T>>;
+
+        // Collateral each seller has locked in each asset, held as reserved balance.
+        Collateral get(collateral): double_map T::AccountId, blake2_256(AssetIdOf<T>) => T::Balance;
+        // Compensation awarded to the buyer of each listing that the seller's collateral didn't
+        // cover: buyer, asset, amount unpaid. Kept after the listing is removed, until it is settled.
+        Shortfalls get(shortfall): map ListingId => Option<(T::AccountId, AssetIdOf<T>, T::Balance)>;
+
+        // The block until which each suspended account may not post or buy listings.
+        Suspensions get(suspended_until): map T::AccountId => Option<T::BlockNumber>;
+
+        // Reviews each sale received, in the order they were given.
+        Reviews get(reviews): map ListingId => Vec<ReviewOf<T>>;
+
+        BlindReviews get(blind_review): map ListingId => Option<BlindReviewOf<T>>;
+        // Blind reviews whose current phase ends at each block. Entries for reviews
+        // whose deadline has since moved are skipped.
+        BlindReviewDeadlines: map T::BlockNumber => Vec<ListingId>;
+
+        // The layout of this module's storage. See `CURRENT_STORAGE_VERSION`.
+        // Zero means the chain predates storage versioning.
+        StorageVersion get(storage_version) build(|_| CURRENT_STORAGE_VERSION): u32;
     }
 }
 
@@ -69,27 +381,155 @@ decl_module! {
         // this is needed only if you are using events in your module
         fn deposit_event() = default;
 
-        /// Post a listing for an item for sale.
-        pub fn post_listing(origin, p: u32, d: u32) -> Result {
+        /// The amount reserved from a seller for each listing.
+        const ListingDeposit: T::Balance = T::ListingDeposit::get();
+
+        /// The share of each sale taken as a fee for the insurance pool.
+        const SaleFee: Perbill = T::SaleFee::get();
+
+        /// The amount reserved for each report.
+        const ReportBond: T::Balance = T::ReportBond::get();
+
+        /// How many reports flag a listing.
+        const ReportThreshold: u32 = T::ReportThreshold::get();
+
+        /// How long buyers have to ask for a return.
+        const ReturnWindow: T::BlockNumber = T::ReturnWindow::get();
+
+        /// The maximum number of tags on a listing.
+        const MaxTags: u32 = T::MaxTags::get();
+
+        /// The maximum number of listings in a `buy_many` call.
+        const MaxCartSize: u32 = T::MaxCartSize::get();
+
+        /// The maximum number of milestones of a service.
+        const MaxMilestones: u32 = T::MaxMilestones::get();
+
+        /// The maximum number of blocks of a single rental.
+        const MaxRentalPeriod: u32 = T::MaxRentalPeriod::get();
+
+        /// The maximum number of subscription payments taken in one block.
+        const MaxPaymentsPerBlock: u32 = T::MaxPaymentsPerBlock::get();
+
+        /// How long each phase of a blind review lasts.
+        const BlindReviewPeriod: T::BlockNumber = T::BlindReviewPeriod::get();
+
+        /// The maximum length of a review comment or rebuttal.
+        const MaxCommentLength: u32 = T::MaxCommentLength::get();
+
+        fn on_initialize(n: T::BlockNumber) {
+            Self::migrate();
+
+            for listing_id in <BlindReviewDeadlines<T>>::take(n) {
+                Self::blind_review_deadline(listing_id, n);
+            }
+
+            // Payments over the limit go first in the next block
+            let mut due = <PaymentsDue<T>>::take(n);
+            let limit = T::MaxPaymentsPerBlock::get() as usize;
+            if due.len() > limit {
+                let mut carried = due.split_off(limit);
+                let next = n.saturating_add(T::BlockNumber::from(1));
+                carried.extend(<PaymentsDue<T>>::take(next));
+                <PaymentsDue<T>>::insert(next, carried);
+            }
+            for (listing_id, subscriber) in due {
+                Self::subscription_payment_due(listing_id, subscriber, n);
+            }
+        }
+
+        fn on_finalize(n: T::BlockNumber) {
+            // Items can be returned up to the end of the block they are due in
+            for listing_id in <RentalsDue<T>>::take(n) {
+                if Statuses::get(listing_id) == Status::Rented && <ReturnDue<T>>::get(listing_id) == Some(n) {
+                    Statuses::insert(listing_id, Status::Overdue);
+                    Self::deposit_event(RawEvent::RentalOverdue(listing_id));
+                }
+            }
+        }
+
+        /// Register a new category that listings may be posted in.
+        pub fn add_category(origin, category: CategoryId, name: u32) -> Result {
+            T::CategoryOrigin::ensure_origin(origin)?;
+            ensure!(!Categories::exists(category), "Category already exists");
+
+            Categories::insert(category, name);
+
+            Self::deposit_event(RawEvent::CategoryAdded(category, name));
+            Ok(())
+        }
+
+        /// Remove a category. No listings in it may be left in storage, sold or not.
+        pub fn remove_category(origin, category: CategoryId) -> Result {
+            T::CategoryOrigin::ensure_origin(origin)?;
+            ensure!(Categories::exists(category), "No such category");
+            ensure!(CategoryListingCounts::get(category) == 0, "Category still has listings");
+
+            Categories::remove(category);
+            VerifiedOnly::remove(category);
+
+            Self::deposit_event(RawEvent::CategoryRemoved(category));
+            Ok(())
+        }
+
+        /// Choose whether only verified sellers may post in a category.
+        pub fn set_verified_only(origin, category: CategoryId, verified_only: bool) -> Result {
+            T::CategoryOrigin::ensure_origin(origin)?;
+            ensure!(Categories::exists(category), "No such category");
+
+            if verified_only {
+                VerifiedOnly::insert(category, true);
+            } else {
+                VerifiedOnly::remove(category);
+            }
+
+            Self::deposit_event(RawEvent::CategoryVerifiedOnly(category, verified_only));
+            Ok(())
+        }
+
+        /// Post a listing for an item for sale, priced in the given asset.
+        pub fn post_listing(origin, p: T::Balance, d: u32, category: CategoryId, tags: Vec<Tag>, asset: AssetIdOf<T>) -> Result {
             let s = ensure_signed(origin)?;
+            Self::do_post(s, p, d, category, tags, asset)?;
+            Ok(())
+        }
 
-            // Construct the listing struct.
-            let listing = Listing::<T::AccountId> {
-                seller: s.clone(),
-                price: p,
-                description: d,
-            };
+        /// Post a listing for a service delivered in stages, priced in the given asset.
+        /// The price is the sum of the milestones' amounts. The buyer pays all of it into
+        /// escrow up front, and each milestone is paid to you once the buyer approves it.
+        pub fn post_service(origin, milestones: Vec<T::Balance>, d: u32, category: CategoryId, tags: Vec<Tag>, asset: AssetIdOf<T>) -> Result {
+            let s = ensure_signed(origin)?;
+            ensure!(!milestones.is_empty(), "A service needs at least one milestone");
+            ensure!(milestones.len() as u32 <= T::MaxMilestones::get(), "Too many milestones");
+            let price = milestones.iter()
+                .try_fold(T::Balance::zero(), |total, amount| total.checked_add(amount))
+                .ok_or("Milestones add up to too much")?;
 
-            // Update the listing_id
-            let listing_id = NextId::get();
-            NextId::put(listing_id.wrapping_add(1));
+            let listing_id = Self::do_post(s, price, d, category, tags, asset)?;
+            <Milestones<T>>::insert(listing_id, milestones.into_iter()
+                .map(|amount| Milestone { amount, paid: Zero::zero(), state: MilestoneState::Pending })
+                .collect::<Vec<_>>());
+            Ok(())
+        }
 
-            // Insert the new listing
-            <Listings<T>>::insert(listing_id, listing.clone());
-            Statuses::insert(listing_id, Status::Active);
+        /// Post a listing for an item to rent out, for `rate` per block of the given asset.
+        /// Renters also put down `deposit`, which is returned once you confirm the item is
+        /// back, or yours to claim if it is overdue.
+        pub fn post_rental(origin, rate: T::Balance, deposit: T::Balance, d: u32, category: CategoryId, tags: Vec<Tag>, asset: AssetIdOf<T>) -> Result {
+            let s = ensure_signed(origin)?;
+            let listing_id = Self::do_post(s, rate, d, category, tags, asset)?;
+            <RentalDeposits<T>>::insert(listing_id, deposit);
+            Ok(())
+        }
 
-            // Raise the event
-            Self::deposit_event(RawEvent::Posted(s, listing_id, listing));
+        /// Post a listing for a membership or service that subscribers pay `p` for every
+        /// `period` blocks, priced in the given asset.
+        pub fn post_subscription(origin, p: T::Balance, period: T::BlockNumber, d: u32, category: CategoryId, tags: Vec<Tag>, asset: AssetIdOf<T>) -> Result {
+            let s = ensure_signed(origin)?;
+            ensure!(!period.is_zero(), "Subscription period must be at least one block");
+            ensure!(!p.is_zero(), "A subscription must have a price");
+            let listing_id = Self::do_post(s, p, d, category, tags, asset)?;
+            <SubscriptionPeriods<T>>::insert(listing_id, period);
             Ok(())
         }
 
@@ -97,13 +537,13 @@ decl_module! {
         pub fn cancel_listing(origin, listing_id: ListingId) -> Result {
             let sender = ensure_signed(origin)?;
             ensure!(<Listings<T>>::exists(listing_id), "No such listing to cancel");
+            ensure!(Statuses::get(listing_id) != Status::Flagged, "Listing is flagged pending moderation");
             ensure!(Statuses::get(listing_id) == Status::Active, "Cannot cancel already-sold listing");
             ensure!(<Listings<T>>::get(listing_id).unwrap().seller == sender, "Cannot cancel another seller's listing");
 
-            // Remove listing from map
-            <Listings<T>>::remove(listing_id);
-            Statuses::remove(listing_id);
-            <Buyers<T>>::remove(listing_id);
+            // Return the deposit and remove listing from storage
+            T::Currency::unreserve(&sender, <Deposits<T>>::get(listing_id));
+            Self::remove_listing(listing_id);
 
             // Emit Event
             Self::deposit_event(RawEvent::Cancelled(listing_id));
@@ -111,149 +551,2567 @@ decl_module! {
 
         }
 
-        /// Buy an item specified by the supplied listing
-        pub fn buy(origin, listing_id: ListingId) -> Result {
-            let buyer = ensure_signed(origin)?;
+        /// Forcibly remove a listing, e.g. because the item is prohibited. The seller's
+        /// deposit is slashed if `slash` is set, and returned otherwise.
+        /// If the listing was already sold, the buyer's payment is refunded.
+        /// Only callable by the moderator origin.
+        pub fn force_remove_listing(origin, listing_id: ListingId, reason: ReasonCode, slash: bool) -> Result {
+            T::ModeratorOrigin::ensure_origin(origin)?;
+            let listing = <Listings<T>>::get(listing_id).ok_or("No such listing to remove")?;
 
-            ensure!(<Listings<T>>::exists(listing_id), "No such listing to buy");
-            ensure!(Statuses::get(listing_id) == Status::Active, "Listing already sold");
-            ensure!(<Listings<T>>::get(listing_id).unwrap().seller != buyer, "Can't buy own listing");
+            Self::force_remove(listing_id, listing, reason, slash);
+            Ok(())
+        }
 
-            // Update storage
-            <Buyers<T>>::insert(listing_id, &buyer);
-            Statuses::insert(listing_id, Status::Sold);
+        /// Report a listing to the moderators, reserving the report bond. A listing with
+        /// `ReportThreshold` reports is flagged, which hides it until the reports are resolved.
+        pub fn report_listing(origin, listing_id: ListingId, reason: ReasonCode) -> Result {
+            let reporter = ensure_signed(origin)?;
+            ensure!(<Listings<T>>::exists(listing_id), "No such listing to report");
+            let status = Statuses::get(listing_id);
+            ensure!(status == Status::Active || status == Status::Flagged, "Only listings for sale can be reported");
+
+            let mut reports = <Reports<T>>::get(listing_id);
+            ensure!(!reports.iter().any(|r| r.reporter == reporter), "You've already reported this listing");
+
+            let bond = T::ReportBond::get();
+            T::Currency::reserve(&reporter, bond)
+                .map_err(|_| "Not enough free balance to reserve the report bond")?;
+            reports.push(Report { reporter: reporter.clone(), reason, bond });
+            let flag = status == Status::Active && reports.len() as u32 >= T::ReportThreshold::get();
+            <Reports<T>>::insert(listing_id, reports);
+
+            Self::deposit_event(RawEvent::Reported(reporter, listing_id, reason));
+
+            if flag {
+                Statuses::insert(listing_id, Status::Flagged);
+                Self::unindex_category(listing_id);
+                Self::dissolve_bundle_of(listing_id);
+                Self::deposit_event(RawEvent::Flagged(listing_id));
+            }
+            Ok(())
+        }
+
+        /// Resolve the reports against a listing. If they are upheld, reporters get their
+        /// bonds back plus a share of the seller's deposit, and the listing is removed with
+        /// the rest of the deposit slashed. Otherwise their bonds are slashed, and a flagged
+        /// listing goes back on sale. Only callable by the moderator origin.
+        pub fn resolve_reports(origin, listing_id: ListingId, upheld: bool, reason: ReasonCode) -> Result {
+            T::ModeratorOrigin::ensure_origin(origin)?;
+            let listing = <Listings<T>>::get(listing_id).ok_or("No such listing")?;
+            let reports = <Reports<T>>::take(listing_id);
+            ensure!(!reports.is_empty(), "Listing has not been reported");
+
+            if upheld {
+                let deposit = <Deposits<T>>::get(listing_id);
+                let share = (T::ReporterReward::get() * deposit) / T::Balance::from(reports.len() as u32);
+                let mut paid = T::Balance::zero();
+                for report in reports.iter() {
+                    T::Currency::unreserve(&report.reporter, report.bond);
+                    // Only what was actually moved counts, if the seller's reserve ran short
+                    if let Ok(remaining) = T::Currency::repatriate_reserved(&listing.seller, &report.reporter, share) {
+                        paid += share - remaining;
+                    }
+                }
+                <Deposits<T>>::insert(listing_id, deposit.saturating_sub(paid));
+                Self::force_remove(listing_id, listing, reason, true);
+            } else {
+                for report in reports.iter() {
+                    T::Currency::slash_reserved(&report.reporter, report.bond);
+                }
+                if Statuses::get(listing_id) == Status::Flagged {
+                    Statuses::insert(listing_id, Status::Active);
+                    Self::index_category(listing_id, listing.category);
+                }
+            }
+
+            Self::deposit_event(RawEvent::ReportsResolved(listing_id, upheld));
+            Ok(())
+        }
+
+        /// Lock collateral in an asset, which moderators can use to compensate buyers
+        /// of your listings priced in that asset.
+        pub fn bond_collateral(origin, asset: AssetIdOf<T>, amount: T::Balance) -> Result {
+            let seller = ensure_signed(origin)?;
+
+            T::Assets::reserve(asset, &seller, amount)
+                .map_err(|_| "Insufficient balance to bond")?;
+            <Collateral<T>>::mutate(&seller, asset, |c| *c += amount);
+
+            Self::deposit_event(RawEvent::CollateralBonded(seller, asset, amount));
+            Ok(())
+        }
+
+        /// Unlock collateral, keeping at least what your listings in that asset require.
+        pub fn unbond_collateral(origin, asset: AssetIdOf<T>, amount: T::Balance) -> Result {
+            let seller = ensure_signed(origin)?;
+
+            let collateral = <Collateral<T>>::get(&seller, asset);
+            ensure!(amount <= collateral, "Not that much collateral bonded");
+            let remaining = collateral - amount;
+            ensure!(
+                remaining >= Self::required_collateral(&seller, asset),
+                "Collateral is still needed for active listings"
+            );
+
+            T::Assets::unreserve(asset, &seller, amount);
+            if remaining.is_zero() {
+                <Collateral<T>>::remove(&seller, asset);
+            } else {
+                <Collateral<T>>::insert(&seller, asset, remaining);
+            }
+
+            Self::deposit_event(RawEvent::CollateralUnbonded(seller, asset, amount));
+            Ok(())
+        }
+
+        /// Compensate the buyer of a listing out of the seller's collateral, e.g. after
+        /// the buyer wins a dispute. Pays at most what is bonded in the listing's asset, and
+        /// records the rest as a shortfall that can be claimed elsewhere, e.g. from insurance.
+        /// Only callable by the moderator origin.
+        pub fn compensate_buyer(origin, listing_id: ListingId, amount: T::Balance) -> Result {
+            T::ModeratorOrigin::ensure_origin(origin)?;
+            let listing = <Listings<T>>::get(listing_id).ok_or("No such listing")?;
+            let buyer = <Buyers<T>>::get(listing_id).ok_or("Listing has not been sold")?;
+
+            let collateral = <Collateral<T>>::get(&listing.seller, listing.asset);
+            let paid = amount.min(collateral);
+            T::Assets::repatriate_reserved(listing.asset, &listing.seller, &buyer, paid)?;
+            <Collateral<T>>::insert(&listing.seller, listing.asset, collateral - paid);
+            if paid < amount {
+                let unpaid = <Shortfalls<T>>::get(listing_id).map_or(Zero::zero(), |(_, _, unpaid)| unpaid);
+                <Shortfalls<T>>::insert(listing_id, (buyer.clone(), listing.asset, unpaid.saturating_add(amount - paid)));
+            }
+
+            Self::deposit_event(RawEvent::BuyerCompensated(buyer, listing_id, paid));
+            Ok(())
+        }
+
+        /// Stop an account from posting or buying listings for the next `blocks` blocks.
+        /// Suspending for zero blocks lifts a suspension. Only callable by the moderator origin.
+        pub fn suspend(origin, who: T::AccountId, blocks: T::BlockNumber) -> Result {
+            T::ModeratorOrigin::ensure_origin(origin)?;
+
+            if blocks.is_zero() {
+                <Suspensions<T>>::remove(&who);
+            } else {
+                let until = <system::Module<T>>::block_number().checked_add(&blocks)
+                    .ok_or("Suspension too long")?;
+                <Suspensions<T>>::insert(&who, until);
+            }
+
+            Self::deposit_event(RawEvent::Suspended(who, blocks));
+            Ok(())
+        }
+
+        /// Change the price and description of an item you have for sale.
+        pub fn update_listing(origin, listing_id: ListingId, new_price: T::Balance, new_description: u32) -> Result {
+            let sender = ensure_signed(origin)?;
+            let mut listing = <Listings<T>>::get(listing_id).ok_or("No such listing to update")?;
+            ensure!(Statuses::get(listing_id) != Status::Flagged, "Listing is flagged pending moderation");
+            ensure!(Statuses::get(listing_id) == Status::Active, "Cannot update already-sold listing");
+            ensure!(listing.seller == sender, "Cannot update another seller's listing");
+            ensure!(
+                new_price == listing.price || !<Milestones<T>>::exists(listing_id),
+                "A service's price is set by its milestones"
+            );
+            ensure!(
+                !new_price.is_zero() || !<SubscriptionPeriods<T>>::exists(listing_id),
+                "A subscription must have a price"
+            );
+
+            let (old_price, old_description) = (listing.price, listing.description);
+            listing.price = new_price;
+            listing.description = new_description;
+            listing.version = listing.version.wrapping_add(1);
+            <Listings<T>>::insert(listing_id, listing);
+            // The bundle's discount was set against the old terms
+            Self::dissolve_bundle_of(listing_id);
 
             // Emit Event
-            Self::deposit_event(RawEvent::Sold(buyer, listing_id));
+            Self::deposit_event(RawEvent::Updated(listing_id, old_price, new_price, old_description, new_description));
             Ok(())
         }
 
-        ///  Submit feedback for your counterparty in the specified transaction.
-        pub fn review(origin, listing_id: ListingId, feedback: FeedbackOf<T>) -> Result {
+        /// Buy an item specified by the supplied listing.
+        /// The purchase fails if the listing costs more than `max_price`, or if it has been
+        /// updated since the buyer saw `expected_version`, so a seller cannot change the
+        /// terms while the buyer's transaction is pending.
+        pub fn buy(origin, listing_id: ListingId, max_price: T::Balance, expected_version: u32) -> Result {
+            let buyer = ensure_signed(origin)?;
+            Self::ensure_not_suspended(&buyer)?;
 
-            enum Role {Buyer, Seller}
+            let listing = Self::ensure_can_buy(&buyer, listing_id, max_price, expected_version)?;
+            Self::do_buy(buyer, listing_id, listing.asset, listing.price)
+        }
 
-            let reviewer = ensure_signed(origin)?;
+        /// Buy several listings at once. Each entry is a listing id with the same
+        /// max price and expected version that `buy` takes.
+        /// Either every listing is bought, or none are.
+        pub fn buy_many(origin, items: Vec<(ListingId, T::Balance, u32)>) -> Result {
+            let buyer = ensure_signed(origin)?;
+            Self::ensure_not_suspended(&buyer)?;
+            ensure!(!items.is_empty(), "No listings to buy");
+            ensure!(items.len() as u32 <= T::MaxCartSize::get(), "Too many listings to buy at once");
 
-            ensure!(<Listings<T>>::exists(listing_id), "No such listing");
-            ensure!(Statuses::get(listing_id) != Status::Active, "Listing is still active");
+            let mut ids = items.iter().map(|(listing_id, _, _)| *listing_id).collect::<Vec<_>>();
+            ids.sort();
+            ids.dedup();
+            ensure!(ids.len() == items.len(), "Listing appears more than once");
 
-            let status = Statuses::get(listing_id);
-            let (role, reviewee) =
-                if <Listings<T>>::get(listing_id).unwrap().seller == reviewer {
-                    (Role::Seller, <Buyers<T>>::get(listing_id).unwrap())
+            // Check every purchase before making any of them
+            let mut purchases = Vec::with_capacity(items.len());
+            let mut totals: Vec<(AssetIdOf<T>, T::Balance)> = Vec::new();
+            for (listing_id, max_price, expected_version) in items {
+                let listing = Self::ensure_can_buy(&buyer, listing_id, max_price, expected_version)?;
+                match totals.iter_mut().find(|(asset, _)| *asset == listing.asset) {
+                    Some((_, total)) => *total = total.saturating_add(listing.price),
+                    None => totals.push((listing.asset, listing.price)),
                 }
-                else if <Buyers<T>>::get(listing_id) == Some(reviewer.clone()) {
-                    (Role::Buyer, <Listings<T>>::get(listing_id).unwrap().seller)
+                purchases.push((listing_id, listing));
+            }
+            // Reserve the payment for all of them before marking any sold, undoing
+            // what was reserved if the buyer can't afford them all
+            let mut reserved = Vec::with_capacity(totals.len());
+            for (asset, total) in totals {
+                if T::Assets::reserve(asset, &buyer, total).is_err() {
+                    for (asset, total) in reserved {
+                        T::Assets::unreserve(asset, &buyer, total);
+                    }
+                    return Err("Insufficient balance to buy every listing");
                 }
-                else {
-                    return Err("You were not involved in this listing");
-                };
+                reserved.push((asset, total));
+            }
 
-            match (status, role) {
-                (Status::Sold, Role::Buyer) => {
-                    Statuses::insert(listing_id, Status::BuyerReviewed);
-                },
-                (Status::Sold, Role::Seller) => {
-                    Statuses::insert(listing_id, Status::SellerReviewed);
-                },
-                (Status::SellerReviewed, Role::Buyer) |
-                (Status::BuyerReviewed, Role::Seller) => {
-                    Statuses::remove(listing_id);
-                    <Listings<T>>::remove(listing_id);
-                    <Buyers<T>>::remove(listing_id);
-                    Self::deposit_event(RawEvent::Settled(reviewer.clone(), listing_id));
-                },
-                _ => return Err("You've already reviewed this listing"),
+            for (listing_id, listing) in purchases {
+                Self::mark_sold(buyer.clone(), listing_id, listing.price);
             }
+            Ok(())
+        }
 
-            // Call into the reputation system
-            let _ = <<T as Trait>::ReputationSystem as Reputation<T::AccountId>>::rate(reviewer, reviewee, feedback);
+        /// Offer several of your active listings together for less than their combined price.
+        pub fn create_bundle(origin, listings: Vec<ListingId>, price: T::Balance) -> Result {
+            let seller = ensure_signed(origin)?;
+            ensure!(listings.len() >= 2, "A bundle needs at least two listings");
+            ensure!(listings.len() as u32 <= T::MaxCartSize::get(), "Too many listings in bundle");
 
+            let mut ids = listings.clone();
+            ids.sort();
+            ids.dedup();
+            ensure!(ids.len() == listings.len(), "Listing appears more than once");
+
+            let mut combined_price = T::Balance::zero();
+            let mut bundle_asset = None;
+            for listing_id in listings.iter() {
+                let listing = <Listings<T>>::get(listing_id).ok_or("No such listing to bundle")?;
+                ensure!(listing.seller == seller, "Cannot bundle another seller's listing");
+                ensure!(
+                    *bundle_asset.get_or_insert(listing.asset) == listing.asset,
+                    "Bundled listings must be priced in the same asset"
+                );
+                ensure!(Statuses::get(listing_id) == Status::Active, "Cannot bundle already-sold listing");
+                ensure!(!ListingBundles::exists(listing_id), "Listing is already in a bundle");
+                ensure!(!<Milestones<T>>::exists(listing_id), "Services can't be bundled");
+                ensure!(!<RentalDeposits<T>>::exists(listing_id), "Rentals can't be bundled");
+                ensure!(!<SubscriptionPeriods<T>>::exists(listing_id), "Subscriptions can't be bundled");
+                combined_price = combined_price.saturating_add(listing.price);
+            }
+            ensure!(price < combined_price, "Bundle price must be less than the listings' combined price");
+
+            let bundle_id = NextBundleId::get();
+            NextBundleId::put(bundle_id.wrapping_add(1));
+            for listing_id in listings.iter() {
+                ListingBundles::insert(listing_id, bundle_id);
+            }
+            <Bundles<T>>::insert(bundle_id, Bundle { seller: seller.clone(), listings: listings.clone(), price });
+
+            Self::deposit_event(RawEvent::BundleCreated(seller, bundle_id, listings, price));
             Ok(())
         }
-    }
-}
 
-decl_event!(
-    pub enum Event<T> where AccountId = <T as system::Trait>::AccountId {
-        Posted(AccountId, ListingId, Listing<AccountId>),
-        Cancelled(ListingId),
-        Sold(AccountId, ListingId),
-        Settled(AccountId, ListingId),
-    }
-);
+        /// Withdraw a bundle you created. Its listings stay for sale individually.
+        pub fn dissolve_bundle(origin, bundle_id: BundleId) -> Result {
+            let sender = ensure_signed(origin)?;
+            let bundle = <Bundles<T>>::get(bundle_id).ok_or("No such bundle")?;
+            ensure!(bundle.seller == sender, "Cannot dissolve another seller's bundle");
 
-/// tests for this module
-#[cfg(test)]
-mod tests {
-    use super::*;
+            Self::remove_bundle(bundle_id, bundle);
+            Ok(())
+        }
 
-    use runtime_io::with_externalities;
-    use primitives::{H256, Blake2Hasher};
-    use support::{impl_outer_origin, assert_ok};
-    use runtime_primitives::{
-        BuildStorage,
-        traits::{BlakeTwo256, IdentityLookup},
-        testing::{Digest, DigestItem, Header}
-    };
+        /// Buy every listing in a bundle for the bundle's price. Fails if the
+        /// bundle costs more than `max_price`.
+        /// The bundle price is split between the member sales in proportion to their
+        /// individual prices, so each sale can still settle on its own.
+        pub fn buy_bundle(origin, bundle_id: BundleId, max_price: T::Balance) -> Result {
+            let buyer = ensure_signed(origin)?;
+            Self::ensure_not_suspended(&buyer)?;
+            let bundle = <Bundles<T>>::get(bundle_id).ok_or("No such bundle")?;
+            ensure!(bundle.seller != buyer, "Can't buy own bundle");
+            ensure!(bundle.price <= max_price, "Bundle price exceeds max price");
 
-    impl_outer_origin! {
-        pub enum Origin for Test {}
-    }
+            // Buying or changing a member dissolves the bundle, but check the members are
+            // all still for sale rather than rely on it.
+            let mut members = Vec::with_capacity(bundle.listings.len());
+            for listing_id in bundle.listings.iter() {
+                let listing = <Listings<T>>::get(listing_id).ok_or("Bundled listing no longer exists")?;
+                ensure!(Statuses::get(listing_id) == Status::Active, "Bundled listing is no longer for sale");
+                members.push((*listing_id, listing));
+            }
+            let asset = members.first().map(|(_, l)| l.asset).ok_or("Bundle is empty")?;
 
-    // For testing the module, we construct most of a mock runtime. This means
-    // first constructing a configuration type (`Test`) which `impl`s each of the
-    // configuration traits of modules we want to use.
-    #[derive(Clone, Eq, PartialEq)]
-    pub struct Test;
-    impl system::Trait for Test {
-        type Origin = Origin;
-        type Index = u64;
-        type BlockNumber = u64;
-        type Hash = H256;
-        type Hashing = BlakeTwo256;
-        type Digest = Digest;
-        type AccountId = u64;
-        type Lookup = IdentityLookup<Self::AccountId>;
-        type Header = Header;
-        type Event = ();
-        type Log = DigestItem;
-    }
+            // Reserve the whole payment before changing anything else
+            T::Assets::reserve(asset, &buyer, bundle.price)
+                .map_err(|_| "Insufficient balance to buy bundle")?;
 
-    impl Reputation<u64> for () {
-        type Score = ();
-        type Feedback = ();
-        fn rate(_rater: u64, _ratee: u64, _feedback: Self::Feedback)
-          -> Result {
-              Ok(())
+            // Take the bundle down first so that buying its members doesn't dissolve it.
+            for listing_id in bundle.listings.iter() {
+                ListingBundles::remove(listing_id);
+            }
+            <Bundles<T>>::remove(bundle_id);
+
+            let combined_price = members.iter()
+                .fold(T::Balance::zero(), |total, (_, l)| total.saturating_add(l.price));
+            let mut remaining = bundle.price;
+            let last = members.len() - 1;
+            for (i, (listing_id, listing)) in members.into_iter().enumerate() {
+                let share = if i == last {
+                    remaining
+                } else {
+                    (Perbill::from_rational_approximation(listing.price, combined_price) * bundle.price).min(remaining)
+                };
+                remaining -= share;
+                Self::mark_sold(buyer.clone(), listing_id, share);
+            }
+
+            Self::deposit_event(RawEvent::BundleSold(buyer, bundle_id));
+            Ok(())
         }
-        fn reputation(_who : u64) -> Self::Score {
-            ()
+
+        /// Rent an item for `blocks` blocks, paying its rate for each into escrow and
+        /// putting down the rental deposit. Like `buy`, fails if the rate exceeds
+        /// `max_rate` or the listing has been updated since `expected_version`.
+        /// The rent is paid to the seller once the item is returned and the rental reviewed.
+        pub fn rent(origin, listing_id: ListingId, blocks: u32, max_rate: T::Balance, expected_version: u32) -> Result {
+            let renter = ensure_signed(origin)?;
+            Self::ensure_not_suspended(&renter)?;
+            let listing = <Listings<T>>::get(listing_id).ok_or("No such listing to rent")?;
+            let deposit = <RentalDeposits<T>>::get(listing_id).ok_or("Listing is not for rent")?;
+            ensure!(Statuses::get(listing_id) != Status::Flagged, "Listing is flagged pending moderation");
+            ensure!(Statuses::get(listing_id) == Status::Active, "Listing already rented");
+            ensure!(listing.seller != renter, "Can't rent own listing");
+            ensure!(listing.price <= max_rate, "Listing rate exceeds max rate");
+            ensure!(listing.version == expected_version, "Listing has been updated");
+            ensure!(blocks > 0, "Must rent for at least one block");
+            ensure!(blocks <= T::MaxRentalPeriod::get(), "Rental period too long");
+            let due = <system::Module<T>>::block_number().checked_add(&T::BlockNumber::from(blocks))
+                .ok_or("Rental period too long")?;
+
+            let rent = listing.price.checked_mul(&T::Balance::from(blocks)).ok_or("Rent is too high")?;
+            T::Assets::reserve(listing.asset, &renter, rent.saturating_add(deposit))
+                .map_err(|_| "Insufficient balance to rent listing")?;
+
+            <Escrow<T>>::insert(listing_id, rent);
+            <Buyers<T>>::insert(listing_id, &renter);
+            <SoldAt<T>>::insert(listing_id, <system::Module<T>>::block_number());
+            <ReturnDue<T>>::insert(listing_id, due);
+            <RentalsDue<T>>::mutate(due, |l| l.push(listing_id));
+            Statuses::insert(listing_id, Status::Rented);
+            Self::unindex_category(listing_id);
+            Self::index_buyer(listing_id, &renter);
+
+            Self::deposit_event(RawEvent::Rented(renter, listing_id, due));
+            Ok(())
         }
-    }
 
-    impl Trait for Test {
-        type ReputationSystem = ();
-        type Event = ();
-    }
-    type Marketplace = Module<Test>;
+        /// Confirm that an item you rented out has been returned, releasing the renter's
+        /// deposit. The rental can then be reviewed and settled like a sale.
+        pub fn confirm_return(origin, listing_id: ListingId) -> Result {
+            let seller = ensure_signed(origin)?;
+            let (listing, renter) = Self::ensure_rented_out(&seller, listing_id)?;
 
-    // This function basically just builds a genesis storage key/value store according to
-    // our desired mockup.
-    fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
-        system::GenesisConfig::<Test>::default().build_storage().unwrap().0.into()
-    }
+            T::Assets::unreserve(listing.asset, &renter, <RentalDeposits<T>>::get(listing_id).unwrap_or_default());
+            Self::end_rental(listing_id);
 
-    #[test]
-    fn listing_id_increments_correctly() {
-        with_externalities(&mut new_test_ext(), || {
+            Self::deposit_event(RawEvent::RentalReturned(listing_id));
+            Ok(())
+        }
 
-            // Post a listing
-            assert_ok!(Marketplace::post_listing(Origin::signed(1), 123, 456));
-            // asserting that the stored value is equal to what we stored
-            //assert_eq!(TemplateModule::something(), Some(42));
+        /// Claim the deposit of an overdue rental of your item. The rental can then be
+        /// reviewed and settled like a sale.
+        pub fn claim_rental_deposit(origin, listing_id: ListingId) -> Result {
+            let seller = ensure_signed(origin)?;
+            let (listing, renter) = Self::ensure_rented_out(&seller, listing_id)?;
+            ensure!(Statuses::get(listing_id) == Status::Overdue, "Rental is not overdue");
+
+            let deposit = <RentalDeposits<T>>::get(listing_id).unwrap_or_default();
+            T::Assets::repatriate_reserved(listing.asset, &renter, &seller, deposit)?;
+            Self::end_rental(listing_id);
+
+            Self::deposit_event(RawEvent::RentalDepositClaimed(listing_id, deposit));
+            Ok(())
+        }
+
+        /// Subscribe to a listing, authorising it to take up to `allowance` from you in total.
+        /// The first period is paid now, and each following one is taken automatically when due,
+        /// until you cancel or the allowance runs out. Like `buy`, fails if the listing costs
+        /// more than `max_price` or has been updated since `expected_version`.
+        pub fn subscribe(origin, listing_id: ListingId, allowance: T::Balance, max_price: T::Balance, expected_version: u32) -> Result {
+            let subscriber = ensure_signed(origin)?;
+            Self::ensure_not_suspended(&subscriber)?;
+            let listing = <Listings<T>>::get(listing_id).ok_or("No such listing to subscribe to")?;
+            let period = <SubscriptionPeriods<T>>::get(listing_id).ok_or("Listing is not a subscription")?;
+            ensure!(Statuses::get(listing_id) != Status::Flagged, "Listing is flagged pending moderation");
+            ensure!(Statuses::get(listing_id) == Status::Active, "Listing is no longer available");
+            ensure!(listing.seller != subscriber, "Can't subscribe to own listing");
+            ensure!(listing.price <= max_price, "Listing price exceeds max price");
+            ensure!(listing.version == expected_version, "Listing has been updated");
+            ensure!(!<Subscriptions<T>>::exists(listing_id, &subscriber), "Already subscribed");
+
+            let mut subscription = Subscription {
+                price: listing.price,
+                allowance,
+                next_payment: <system::Module<T>>::block_number(),
+                paused: false,
+            };
+            Self::pay_subscription(listing_id, &listing, &subscriber, &mut subscription, period)?;
+            <Subscriptions<T>>::insert(listing_id, &subscriber, subscription);
+
+            Self::deposit_event(RawEvent::Subscribed(subscriber, listing_id));
+            Ok(())
+        }
+
+        /// Resume a paused subscription with a new allowance, paying for the current period now.
+        pub fn resume_subscription(origin, listing_id: ListingId, allowance: T::Balance) -> Result {
+            let subscriber = ensure_signed(origin)?;
+            let mut subscription = <Subscriptions<T>>::get(listing_id, &subscriber).ok_or("Not subscribed")?;
+            ensure!(subscription.paused, "Subscription is not paused");
+            let listing = <Listings<T>>::get(listing_id).ok_or("Listing is no longer available")?;
+            let period = <SubscriptionPeriods<T>>::get(listing_id).ok_or("Listing is no longer available")?;
+
+            subscription.allowance = allowance;
+            subscription.next_payment = <system::Module<T>>::block_number();
+            Self::pay_subscription(listing_id, &listing, &subscriber, &mut subscription, period)?;
+            subscription.paused = false;
+            <Subscriptions<T>>::insert(listing_id, &subscriber, subscription);
+
+            Self::deposit_event(RawEvent::SubscriptionResumed(subscriber, listing_id));
+            Ok(())
+        }
+
+        /// Stop paying for a listing you subscribed to.
+        pub fn cancel_subscription(origin, listing_id: ListingId) -> Result {
+            let subscriber = ensure_signed(origin)?;
+            ensure!(<Subscriptions<T>>::exists(listing_id, &subscriber), "Not subscribed");
+
+            <Subscriptions<T>>::remove(listing_id, &subscriber);
+
+            Self::deposit_event(RawEvent::SubscriptionCancelled(subscriber, listing_id));
+            Ok(())
+        }
+
+        /// Ask to return an item you bought. Only possible within the return window,
+        /// and before either party has reviewed the sale.
+        pub fn request_return(origin, listing_id: ListingId) -> Result {
+            let buyer = ensure_signed(origin)?;
+            ensure!(<Buyers<T>>::get(listing_id) == Some(buyer.clone()), "You did not buy this listing");
+            ensure!(Statuses::get(listing_id) == Status::Sold, "Listing can no longer be returned");
+            ensure!(!<Milestones<T>>::exists(listing_id), "Services are disputed by milestone instead");
+            ensure!(!<RentalDeposits<T>>::exists(listing_id), "Rented items are returned to the seller instead");
+            let sold_at = <SoldAt<T>>::get(listing_id).ok_or("Return window has passed")?;
+            ensure!(
+                <system::Module<T>>::block_number() <= sold_at + T::ReturnWindow::get(),
+                "Return window has passed"
+            );
+
+            Statuses::insert(listing_id, Status::ReturnRequested);
+
+            Self::deposit_event(RawEvent::ReturnRequested(buyer, listing_id));
+            Ok(())
+        }
+
+        /// Accept a return of an item you sold. The buyer's payment is refunded,
+        /// and the listing goes back on sale, still holding your deposit.
+        pub fn approve_return(origin, listing_id: ListingId) -> Result {
+            let seller = ensure_signed(origin)?;
+            let listing = <Listings<T>>::get(listing_id).ok_or("No such listing")?;
+            ensure!(listing.seller == seller, "Cannot approve a return of another seller's listing");
+            ensure!(Statuses::get(listing_id) == Status::ReturnRequested, "No return has been requested");
+
+            let buyer = <Buyers<T>>::get(listing_id).ok_or("No return has been requested")?;
+            T::Assets::unreserve(listing.asset, &buyer, <Escrow<T>>::take(listing_id));
+            Self::unindex_buyer(listing_id, &buyer);
+            <Buyers<T>>::remove(listing_id);
+            <SoldAt<T>>::remove(listing_id);
+            Statuses::insert(listing_id, Status::Active);
+            Self::index_category(listing_id, listing.category);
+
+            Self::deposit_event(RawEvent::Returned(listing_id));
+            Ok(())
+        }
+
+        /// Refuse a return of an item you sold. The sale goes ahead as normal.
+        pub fn reject_return(origin, listing_id: ListingId) -> Result {
+            let seller = ensure_signed(origin)?;
+            let listing = <Listings<T>>::get(listing_id).ok_or("No such listing")?;
+            ensure!(listing.seller == seller, "Cannot reject a return of another seller's listing");
+            ensure!(Statuses::get(listing_id) == Status::ReturnRequested, "No return has been requested");
+
+            Statuses::insert(listing_id, Status::Sold);
+
+            Self::deposit_event(RawEvent::ReturnRejected(listing_id));
+            Ok(())
+        }
+
+        /// Approve a milestone of a service you bought, paying its amount to the seller.
+        /// Approving a disputed milestone withdraws the dispute.
+        pub fn approve_milestone(origin, listing_id: ListingId, index: u32) -> Result {
+            let buyer = ensure_signed(origin)?;
+            let (listing, mut milestones) = Self::ensure_milestone(listing_id, index)?;
+            ensure!(<Buyers<T>>::get(listing_id) == Some(buyer.clone()), "You did not buy this service");
+            let milestone = &mut milestones[index as usize];
+            ensure!(
+                milestone.state == MilestoneState::Pending || milestone.state == MilestoneState::Disputed,
+                "Milestone has already been settled"
+            );
+
+            let amount = milestone.amount;
+            Self::pay_seller(listing.asset, &buyer, &listing.seller, amount)?;
+            <Escrow<T>>::mutate(listing_id, |escrow| *escrow -= amount);
+            milestone.paid = amount;
+            milestone.state = MilestoneState::Approved;
+
+            Self::deposit_event(RawEvent::MilestoneApproved(listing_id, index, amount));
+            Self::update_milestones(listing_id, milestones);
+            Ok(())
+        }
+
+        /// Dispute a milestone of a service you bought, leaving a moderator to decide how
+        /// much of it the seller is paid.
+        pub fn dispute_milestone(origin, listing_id: ListingId, index: u32) -> Result {
+            let buyer = ensure_signed(origin)?;
+            let (_, mut milestones) = Self::ensure_milestone(listing_id, index)?;
+            ensure!(<Buyers<T>>::get(listing_id) == Some(buyer.clone()), "You did not buy this service");
+            let milestone = &mut milestones[index as usize];
+            ensure!(milestone.state == MilestoneState::Pending, "Milestone can no longer be disputed");
+
+            milestone.state = MilestoneState::Disputed;
+            <Milestones<T>>::insert(listing_id, milestones);
+
+            Self::deposit_event(RawEvent::MilestoneDisputed(buyer, listing_id, index));
+            Ok(())
+        }
+
+        /// Settle a disputed milestone, paying `to_seller` of its amount to the seller and
+        /// refunding the rest to the buyer. Only callable by the moderator origin.
+        pub fn resolve_milestone(origin, listing_id: ListingId, index: u32, to_seller: T::Balance) -> Result {
+            T::ModeratorOrigin::ensure_origin(origin)?;
+            let (listing, mut milestones) = Self::ensure_milestone(listing_id, index)?;
+            let milestone = &mut milestones[index as usize];
+            ensure!(milestone.state == MilestoneState::Disputed, "Milestone is not disputed");
+            ensure!(to_seller <= milestone.amount, "Can't pay the seller more than the milestone");
+
+            let buyer = <Buyers<T>>::get(listing_id).ok_or("Service is not in progress")?;
+            let amount = milestone.amount;
+            Self::pay_seller(listing.asset, &buyer, &listing.seller, to_seller)?;
+            T::Assets::unreserve(listing.asset, &buyer, amount - to_seller);
+            <Escrow<T>>::mutate(listing_id, |escrow| *escrow -= amount);
+            milestone.paid = to_seller;
+            milestone.state = MilestoneState::Resolved;
+
+            Self::deposit_event(RawEvent::MilestoneResolved(listing_id, index, to_seller, amount - to_seller));
+            Self::update_milestones(listing_id, milestones);
+            Ok(())
+        }
+
+        ///  Submit feedback for your counterparty in the specified transaction.
+        pub fn review(origin, listing_id: ListingId, feedback: FeedbackOf<T>, comment: Vec<u8>) -> Result {
+            let reviewer = ensure_signed(origin)?;
+            ensure!(!<BlindReviews<T>>::exists(listing_id), "Reviews of this listing are blind");
+            ensure!(comment.len() <= T::MaxCommentLength::get() as usize, "Comment too long");
+
+            let reviewee = Self::mark_reviewed(&reviewer, listing_id)?;
+            Self::record_review(listing_id, reviewer.clone(), reviewee.clone(), feedback.clone(), comment);
+
+            // Call into the reputation system
+            let _ = T::ReputationSystem::rate_in_context(reviewer, reviewee, feedback, listing_id);
+
+            Ok(())
+        }
+
+        /// Respond to a review you received. Each review can be rebutted once.
+        pub fn rebut(origin, listing_id: ListingId, rebuttal: Vec<u8>) -> Result {
+            let reviewee = ensure_signed(origin)?;
+            ensure!(rebuttal.len() <= T::MaxCommentLength::get() as usize, "Rebuttal too long");
+
+            let mut reviews = <Reviews<T>>::get(listing_id);
+            let review = reviews.iter_mut().find(|r| r.reviewee == reviewee)
+                .ok_or("You have not been reviewed for this listing")?;
+            ensure!(review.rebuttal.is_none(), "You've already rebutted this review");
+            review.rebuttal = Some(rebuttal);
+            <Reviews<T>>::insert(listing_id, reviews);
+
+            Self::deposit_event(RawEvent::Rebutted(reviewee, listing_id));
+            Ok(())
+        }
+
+        /// Review a sale blind, by committing to the hash of `(feedback, salt)`, or of
+        /// `(feedback, comment, salt)` for a review with a comment. Feedback is revealed with
+        /// `reveal_review` once the other party has committed too, so neither can see the
+        /// other's review before giving their own. Only possible if neither party has
+        /// reviewed openly.
+        pub fn commit_review(origin, listing_id: ListingId, commitment: T::Hash) -> Result {
+            let reviewer = ensure_signed(origin)?;
+
+            let listing = <Listings<T>>::get(listing_id).ok_or("No such listing")?;
+            let existing = <BlindReviews<T>>::get(listing_id);
+            ensure!(existing.is_some() || Statuses::get(listing_id) == Status::Sold, "Listing is being reviewed openly");
+            ensure!(!existing.as_ref().map_or(false, |b| b.revealing), "Too late to commit");
+            let buyer = <Buyers<T>>::get(listing_id).ok_or("Listing is still active")?;
+
+            let now = <system::Module<T>>::block_number();
+            let deadline = now.checked_add(&T::BlindReviewPeriod::get()).ok_or("Blind review period too long")?;
+
+            // May settle the sale and remove the listing
+            Self::mark_reviewed(&reviewer, listing_id)?;
+
+            let mut blind = existing.unwrap_or_else(|| BlindReview {
+                seller: listing.seller.clone(),
+                buyer,
+                seller_commitment: None,
+                buyer_commitment: None,
+                seller_feedback: None,
+                buyer_feedback: None,
+                revealing: false,
+                deadline,
+            });
+            if reviewer == listing.seller {
+                blind.seller_commitment = Some(commitment);
+            } else {
+                blind.buyer_commitment = Some(commitment);
+            }
+            if blind.seller_commitment.is_some() && blind.buyer_commitment.is_some() {
+                blind.revealing = true;
+                blind.deadline = deadline;
+            }
+            <BlindReviewDeadlines<T>>::mutate(blind.deadline, |l| l.push(listing_id));
+            <BlindReviews<T>>::insert(listing_id, blind);
+
+            Self::deposit_event(RawEvent::ReviewCommitted(reviewer, listing_id));
+            Ok(())
+        }
+
+        /// Reveal feedback committed to with `commit_review`. Once both parties have
+        /// revealed, or the reveal period ends, revealed feedback is given to the reputation system.
+        pub fn reveal_review(origin, listing_id: ListingId, feedback: FeedbackOf<T>, comment: Vec<u8>, salt: T::Hash) -> Result {
+            let reviewer = ensure_signed(origin)?;
+            ensure!(comment.len() <= T::MaxCommentLength::get() as usize, "Comment too long");
+
+            let mut blind = <BlindReviews<T>>::get(listing_id).ok_or("No blind review of this listing")?;
+            ensure!(blind.revealing, "Both parties must commit before revealing");
+
+            let commitment = if comment.is_empty() {
+                T::Hashing::hash_of(&(&feedback, &salt))
+            } else {
+                T::Hashing::hash_of(&(&feedback, &comment, &salt))
+            };
+            let (expected, revealed, reviewee) = if reviewer == blind.seller {
+                (blind.seller_commitment, &mut blind.seller_feedback, blind.buyer.clone())
+            } else if reviewer == blind.buyer {
+                (blind.buyer_commitment, &mut blind.buyer_feedback, blind.seller.clone())
+            } else {
+                return Err("You were not involved in this listing");
+            };
+            ensure!(expected.is_some(), "You did not commit to a review");
+            ensure!(revealed.is_none(), "You've already revealed your review");
+            ensure!(expected == Some(commitment), "Feedback does not match commitment");
+            *revealed = Some(feedback.clone());
+            Self::record_review(listing_id, reviewer.clone(), reviewee, feedback, comment);
+
+            Self::deposit_event(RawEvent::ReviewRevealed(reviewer, listing_id));
+
+            let committed = blind.seller_commitment.is_some() as u8 + blind.buyer_commitment.is_some() as u8;
+            let revealed = blind.seller_feedback.is_some() as u8 + blind.buyer_feedback.is_some() as u8;
+            if revealed == committed {
+                Self::close_blind_review(listing_id, blind);
+            } else {
+                <BlindReviews<T>>::insert(listing_id, blind);
+            }
+            Ok(())
+        }
+    }
+}
+
+impl<T: Trait> Module<T> {
+    /// Record that `reviewer` has reviewed a sold listing, settling the sale once both
+    /// parties have. Returns the account being reviewed.
+    fn mark_reviewed(reviewer: &T::AccountId, listing_id: ListingId) -> result::Result<T::AccountId, &'static str> {
+
+        enum Role {Buyer, Seller}
+
+        ensure!(<Listings<T>>::exists(listing_id), "No such listing");
+        ensure!(<Buyers<T>>::exists(listing_id), "Listing is still active");
+        ensure!(Statuses::get(listing_id) != Status::ReturnRequested, "A return has been requested");
+        ensure!(Statuses::get(listing_id) != Status::InProgress, "Milestones are still outstanding");
+        ensure!(
+            Statuses::get(listing_id) != Status::Rented && Statuses::get(listing_id) != Status::Overdue,
+            "Rented item has not been returned"
+        );
+
+        let status = Statuses::get(listing_id);
+        let (role, reviewee) =
+            if <Listings<T>>::get(listing_id).unwrap().seller == *reviewer {
+                (Role::Seller, <Buyers<T>>::get(listing_id).unwrap())
+            }
+            else if <Buyers<T>>::get(listing_id).as_ref() == Some(reviewer) {
+                (Role::Buyer, <Listings<T>>::get(listing_id).unwrap().seller)
+            }
+            else {
+                return Err("You were not involved in this listing");
+            };
+
+        match (status, role) {
+            (Status::Sold, Role::Buyer) => {
+                Statuses::insert(listing_id, Status::BuyerReviewed);
+            },
+            (Status::Sold, Role::Seller) => {
+                Statuses::insert(listing_id, Status::SellerReviewed);
+            },
+            (Status::SellerReviewed, Role::Buyer) |
+            (Status::BuyerReviewed, Role::Seller) => {
+                let listing = <Listings<T>>::get(listing_id).unwrap();
+                let buyer = <Buyers<T>>::get(listing_id).unwrap();
+                // Release the payment to the seller and the deposit back to them
+                let escrow = <Escrow<T>>::get(listing_id);
+                Self::pay_seller(listing.asset, &buyer, &listing.seller, escrow)?;
+                T::Currency::unreserve(&listing.seller, <Deposits<T>>::get(listing_id));
+                // Milestones of a service were paid out of escrow as they were settled
+                let price = <Milestones<T>>::get(listing_id).iter()
+                    .fold(escrow, |total, milestone| total + milestone.paid);
+                Self::archive_sale(listing_id, listing.seller, buyer, listing.asset, price);
+                Self::remove_listing(listing_id);
+                Self::deposit_event(RawEvent::Settled(reviewer.clone(), listing_id));
+            },
+            _ => return Err("You've already reviewed this listing"),
+        }
+
+        Ok(reviewee)
+    }
+
+    /// Post a listing for `seller`, reserving the listing deposit. Returns the new listing's id.
+    fn do_post(s: T::AccountId, p: T::Balance, d: u32, category: CategoryId, tags: Vec<Tag>, asset: AssetIdOf<T>)
+        -> result::Result<ListingId, &'static str>
+    {
+        Self::ensure_not_suspended(&s)?;
+        ensure!(T::Assets::exists(asset), "No such asset");
+        ensure!(Categories::exists(category), "No such category");
+        let verified = T::Verification::is_verified(&s);
+        ensure!(verified || !VerifiedOnly::get(category), "Only verified sellers may post in this category");
+        ensure!(tags.len() as u32 <= T::MaxTags::get(), "Too many tags");
+
+        // Reserve the listing deposit before touching storage
+        let deposit = T::ListingDeposit::get();
+        T::Currency::reserve(&s, deposit)
+            .map_err(|_| "Not enough free balance to reserve the listing deposit")?;
+
+        // Construct the listing struct.
+        let listing = ListingOf::<T> {
+            seller: s.clone(),
+            price: p,
+            asset,
+            description: d,
+            category,
+            tags,
+            version: 0,
+        };
+
+        // Update the listing_id
+        let listing_id = NextId::get();
+        NextId::put(listing_id.wrapping_add(1));
+
+        // Insert the new listing
+        <Listings<T>>::insert(listing_id, listing.clone());
+        Statuses::insert(listing_id, Status::Active);
+        <Deposits<T>>::insert(listing_id, deposit);
+        Self::index_category(listing_id, category);
+        CategoryListingCounts::mutate(category, |c| *c += 1);
+        Self::index_seller(listing_id, &s);
+
+        // Raise the event
+        Self::deposit_event(RawEvent::Posted(s, listing_id, listing, verified));
+        Ok(listing_id)
+    }
+
+    /// Pay `amount` of `asset` out of `buyer`'s reserved balance to `seller`, less the
+    /// sale fee, which goes to the insurance pool.
+    fn pay_seller(asset: AssetIdOf<T>, buyer: &T::AccountId, seller: &T::AccountId, amount: T::Balance) -> Result {
+        let fee = T::SaleFee::get() * amount;
+        T::Assets::repatriate_reserved(asset, buyer, seller, amount - fee)?;
+        // If the fee can't be paid into the pool it goes to the seller rather than staying locked
+        if !fee.is_zero() && T::Assets::repatriate_reserved(asset, buyer, &T::InsurancePool::get(), fee).is_err() {
+            T::Assets::repatriate_reserved(asset, buyer, seller, fee)?;
+            Self::deposit_event(RawEvent::SaleFeeNotPaid(seller.clone(), asset, fee));
+        }
+        Ok(())
+    }
+
+    /// Check that a service is in progress and has a milestone at `index`, returning
+    /// the listing and its milestones.
+    fn ensure_milestone(listing_id: ListingId, index: u32)
+        -> result::Result<(ListingOf<T>, Vec<MilestoneOf<T>>), &'static str>
+    {
+        let listing = <Listings<T>>::get(listing_id).ok_or("No such listing")?;
+        ensure!(Statuses::get(listing_id) == Status::InProgress, "Service is not in progress");
+        let milestones = <Milestones<T>>::get(listing_id);
+        ensure!((index as usize) < milestones.len(), "No such milestone");
+        Ok((listing, milestones))
+    }
+
+    /// Take one period's payment for a subscription out of its allowance, and schedule the next.
+    /// Nothing is changed if the payment fails. The whole payment, fee included, is reserved
+    /// before any of it moves, and then paid out like a sale.
+    fn pay_subscription(listing_id: ListingId, listing: &ListingOf<T>, subscriber: &T::AccountId, subscription: &mut SubscriptionOf<T>, period: T::BlockNumber) -> Result {
+        ensure!(subscription.allowance >= subscription.price, "Allowance is used up");
+        let next_payment = subscription.next_payment.checked_add(&period)
+            .ok_or("Subscription period too long")?;
+
+        T::Assets::reserve(listing.asset, subscriber, subscription.price)
+            .map_err(|_| "Insufficient balance to pay for subscription")?;
+        if let Err(e) = Self::pay_seller(listing.asset, subscriber, &listing.seller, subscription.price) {
+            T::Assets::unreserve(listing.asset, subscriber, subscription.price);
+            return Err(e);
+        }
+
+        subscription.allowance -= subscription.price;
+        subscription.next_payment = next_payment;
+        // A payment carried over past its period is taken in the next block instead
+        let due = next_payment.max(<system::Module<T>>::block_number().saturating_add(T::BlockNumber::from(1)));
+        <PaymentsDue<T>>::mutate(due, |l| l.push((listing_id, subscriber.clone())));
+
+        Self::deposit_event(RawEvent::SubscriptionPaid(subscriber.clone(), listing_id, subscription.price));
+        Ok(())
+    }
+
+    /// Take a subscription payment due by block `n`, pausing the subscription if it fails.
+    fn subscription_payment_due(listing_id: ListingId, subscriber: T::AccountId, n: T::BlockNumber) {
+        let mut subscription = match <Subscriptions<T>>::get(listing_id, &subscriber) {
+            Some(subscription) if !subscription.paused && subscription.next_payment <= n => subscription,
+            _ => return,
+        };
+
+        // Subscriptions end with their listing
+        let (listing, period) = match (<Listings<T>>::get(listing_id), <SubscriptionPeriods<T>>::get(listing_id)) {
+            (Some(listing), Some(period)) => (listing, period),
+            _ => {
+                <Subscriptions<T>>::remove(listing_id, &subscriber);
+                return;
+            },
+        };
+
+        if Self::pay_subscription(listing_id, &listing, &subscriber, &mut subscription, period).is_err() {
+            subscription.paused = true;
+            Self::deposit_event(RawEvent::SubscriptionPaused(subscriber.clone(), listing_id));
+        }
+        <Subscriptions<T>>::insert(listing_id, &subscriber, subscription);
+    }
+
+    /// Check that `seller` has rented out a listing that is yet to be returned,
+    /// returning the listing and its renter.
+    fn ensure_rented_out(seller: &T::AccountId, listing_id: ListingId)
+        -> result::Result<(ListingOf<T>, T::AccountId), &'static str>
+    {
+        let listing = <Listings<T>>::get(listing_id).ok_or("No such listing")?;
+        ensure!(listing.seller == *seller, "Cannot end a rental of another seller's listing");
+        let status = Statuses::get(listing_id);
+        ensure!(status == Status::Rented || status == Status::Overdue, "Listing is not rented out");
+        let renter = <Buyers<T>>::get(listing_id).ok_or("Listing is not rented out")?;
+        Ok((listing, renter))
+    }
+
+    /// Mark a rental as over once its deposit has been dealt with, leaving it to be settled like a sale.
+    /// Its rental deposit is kept as the listing's terms, which also mark it as a rental.
+    fn end_rental(listing_id: ListingId) {
+        <ReturnDue<T>>::remove(listing_id);
+        Statuses::insert(listing_id, Status::Sold);
+    }
+
+    /// Store a service's milestones, completing the service once every one is settled.
+    fn update_milestones(listing_id: ListingId, milestones: Vec<MilestoneOf<T>>) {
+        let completed = milestones.iter()
+            .all(|m| m.state == MilestoneState::Approved || m.state == MilestoneState::Resolved);
+        <Milestones<T>>::insert(listing_id, milestones);
+        if completed {
+            Statuses::insert(listing_id, Status::Sold);
+            Self::deposit_event(RawEvent::ServiceCompleted(listing_id));
+        }
+    }
+
+    /// Keep a record of a settled sale, and add it to both parties' trade histories.
+    fn archive_sale(listing_id: ListingId, seller: T::AccountId, buyer: T::AccountId, asset: AssetIdOf<T>, price: T::Balance) {
+        let mut sale = Sale {
+            seller: seller.clone(),
+            buyer: buyer.clone(),
+            asset,
+            price,
+            settled: <system::Module<T>>::block_number(),
+            seller_feedback: None,
+            buyer_feedback: None,
+        };
+        for review in <Reviews<T>>::get(listing_id) {
+            Self::add_feedback_to_sale(&mut sale, review.reviewer, review.feedback);
+        }
+        <Sales<T>>::insert(listing_id, sale);
+
+        for who in [seller, buyer].iter() {
+            let position = <TradeCounts<T>>::get(who);
+            <Trades<T>>::insert(who, position, listing_id);
+            <TradeCounts<T>>::insert(who, position + 1);
+        }
+    }
+
+    fn add_feedback_to_sale(sale: &mut SaleOf<T>, reviewer: T::AccountId, feedback: FeedbackOf<T>) {
+        if reviewer == sale.seller {
+            sale.seller_feedback = Some(feedback);
+        } else {
+            sale.buyer_feedback = Some(feedback);
+        }
+    }
+
+    /// Keep a record of a review for as long as the chain lives.
+    fn record_review(listing_id: ListingId, reviewer: T::AccountId, reviewee: T::AccountId, feedback: FeedbackOf<T>, comment: Vec<u8>) {
+        // The sale may have settled before this review, e.g. when reviewing blind
+        <Sales<T>>::mutate(listing_id, |sale| if let Some(sale) = sale {
+            Self::add_feedback_to_sale(sale, reviewer.clone(), feedback.clone());
+        });
+        <Reviews<T>>::mutate(listing_id, |reviews| reviews.push(Review {
+            reviewer,
+            reviewee,
+            feedback,
+            comment,
+            rebuttal: None,
+        }));
+    }
+
+    /// Move a blind review on when its current phase ends at block `n`.
+    fn blind_review_deadline(listing_id: ListingId, n: T::BlockNumber) {
+        let mut blind = match <BlindReviews<T>>::get(listing_id) {
+            Some(blind) if blind.deadline == n => blind,
+            _ => return,
+        };
+
+        if blind.revealing {
+            Self::close_blind_review(listing_id, blind);
+        } else {
+            // The other party never committed. Let the one who did reveal anyway.
+            blind.revealing = true;
+            blind.deadline = n.saturating_add(T::BlindReviewPeriod::get());
+            <BlindReviewDeadlines<T>>::mutate(blind.deadline, |l| l.push(listing_id));
+            <BlindReviews<T>>::insert(listing_id, blind);
+        }
+    }
+
+    /// Give whatever feedback was revealed to the reputation system, and forget the blind review.
+    fn close_blind_review(listing_id: ListingId, blind: BlindReviewOf<T>) {
+        if let Some(feedback) = blind.seller_feedback {
+            let _ = T::ReputationSystem::rate_in_context(blind.seller.clone(), blind.buyer.clone(), feedback, listing_id);
+        }
+        if let Some(feedback) = blind.buyer_feedback {
+            let _ = T::ReputationSystem::rate_in_context(blind.buyer, blind.seller, feedback, listing_id);
+        }
+        <BlindReviews<T>>::remove(listing_id);
+
+        Self::deposit_event(RawEvent::BlindReviewClosed(listing_id));
+    }
+
+    /// Remove a listing on a moderator's behalf, refunding any buyer and slashing or returning the deposit.
+    fn force_remove(listing_id: ListingId, listing: ListingOf<T>, reason: ReasonCode, slash: bool) {
+        if let Some(buyer) = <Buyers<T>>::get(listing_id) {
+            T::Assets::unreserve(listing.asset, &buyer, <Escrow<T>>::get(listing_id));
+            // A renter still holding the item gets their deposit back too
+            let status = Statuses::get(listing_id);
+            if status == Status::Rented || status == Status::Overdue {
+                T::Assets::unreserve(listing.asset, &buyer, <RentalDeposits<T>>::get(listing_id).unwrap_or_default());
+            }
+        }
+
+        // Slash or return the deposit and remove listing from storage
+        let deposit = <Deposits<T>>::get(listing_id);
+        let slashed = if slash {
+            let (_, unslashed) = T::Currency::slash_reserved(&listing.seller, deposit);
+            deposit - unslashed
+        } else {
+            T::Currency::unreserve(&listing.seller, deposit);
+            Zero::zero()
+        };
+        Self::remove_listing(listing_id);
+
+        // Emit Event
+        Self::deposit_event(RawEvent::Removed(listing_id, reason, slashed));
+    }
+
+    /// The collateral `seller` must keep bonded in `asset` for their listings that are
+    /// on sale or awaiting settlement in that asset.
+    pub fn required_collateral(seller: &T::AccountId, asset: AssetIdOf<T>) -> T::Balance {
+        let value = (0..<SellerListingCounts<T>>::get(seller))
+            .filter_map(|position| <Listings<T>>::get(<SellerListings<T>>::get(seller, position)))
+            .filter(|listing| listing.asset == asset)
+            .fold(T::Balance::zero(), |total, listing| total.saturating_add(listing.price));
+        T::CollateralRatio::get() * value
+    }
+
+    /// Whether `seller` has bonded enough collateral for all their listings.
+    pub fn is_bonded(seller: &T::AccountId) -> bool {
+        let assets = (0..<SellerListingCounts<T>>::get(seller))
+            .filter_map(|position| <Listings<T>>::get(<SellerListings<T>>::get(seller, position)))
+            .map(|listing| listing.asset)
+            .collect::<Vec<_>>();
+        !assets.is_empty() && assets.into_iter()
+            .all(|asset| <Collateral<T>>::get(seller, asset) >= Self::required_collateral(seller, asset))
+    }
+
+    /// Check that `who` is not currently suspended by a moderator.
+    fn ensure_not_suspended(who: &T::AccountId) -> Result {
+        if let Some(until) = <Suspensions<T>>::get(who) {
+            ensure!(<system::Module<T>>::block_number() >= until, "Account is suspended");
+        }
+        Ok(())
+    }
+
+    /// Check that `buyer` may buy the listing on the terms they saw, returning the listing.
+    fn ensure_can_buy(buyer: &T::AccountId, listing_id: ListingId, max_price: T::Balance, expected_version: u32)
+        -> result::Result<ListingOf<T>, &'static str>
+    {
+        let listing = <Listings<T>>::get(listing_id).ok_or("No such listing to buy")?;
+        ensure!(Statuses::get(listing_id) != Status::Flagged, "Listing is flagged pending moderation");
+        ensure!(Statuses::get(listing_id) == Status::Active, "Listing already sold");
+        ensure!(listing.seller != *buyer, "Can't buy own listing");
+        ensure!(!<RentalDeposits<T>>::exists(listing_id), "Listing is for rent");
+        ensure!(!<SubscriptionPeriods<T>>::exists(listing_id), "Listing is a subscription");
+        ensure!(listing.price <= max_price, "Listing price exceeds max price");
+        ensure!(listing.version == expected_version, "Listing has been updated");
+        Ok(listing)
+    }
+
+    /// Take `amount` of `asset` from `buyer` into escrow and mark the listing sold to them.
+    /// Callers must check `ensure_can_buy` first.
+    fn do_buy(buyer: T::AccountId, listing_id: ListingId, asset: AssetIdOf<T>, amount: T::Balance) -> Result {
+        T::Assets::reserve(asset, &buyer, amount)
+            .map_err(|_| "Insufficient balance to pay for listing")?;
+        Self::mark_sold(buyer, listing_id, amount);
+        Ok(())
+    }
+
+    /// Mark a listing sold to `buyer`, holding `amount` of their reserved balance as its escrow.
+    /// Callers must check `ensure_can_buy` and reserve the amount first.
+    fn mark_sold(buyer: T::AccountId, listing_id: ListingId, amount: T::Balance) {
+        Self::dissolve_bundle_of(listing_id);
+
+        // Update storage
+        <Escrow<T>>::insert(listing_id, amount);
+        <Buyers<T>>::insert(listing_id, &buyer);
+        <SoldAt<T>>::insert(listing_id, <system::Module<T>>::block_number());
+        let status = if <Milestones<T>>::exists(listing_id) { Status::InProgress } else { Status::Sold };
+        Statuses::insert(listing_id, status);
+        Self::unindex_category(listing_id);
+        Self::index_buyer(listing_id, &buyer);
+
+        // Emit Event
+        Self::deposit_event(RawEvent::Sold(buyer, listing_id));
+    }
+
+    /// Remove every storage entry belonging to a listing.
+    /// Callers are responsible for releasing or slashing its deposit, and
+    /// settling or refunding its escrow, first.
+    fn remove_listing(listing_id: ListingId) {
+        Self::dissolve_bundle_of(listing_id);
+        Self::unindex_category(listing_id);
+        if let Some(listing) = <Listings<T>>::get(listing_id) {
+            Self::unindex_seller(listing_id, &listing.seller);
+            CategoryListingCounts::mutate(listing.category, |c| *c = c.saturating_sub(1));
+        }
+        if let Some(buyer) = <Buyers<T>>::get(listing_id) {
+            Self::unindex_buyer(listing_id, &buyer);
+        }
+        <Listings<T>>::remove(listing_id);
+        Statuses::remove(listing_id);
+        <Buyers<T>>::remove(listing_id);
+        <Deposits<T>>::remove(listing_id);
+        <Escrow<T>>::remove(listing_id);
+        <SoldAt<T>>::remove(listing_id);
+        <Milestones<T>>::remove(listing_id);
+        <RentalDeposits<T>>::remove(listing_id);
+        <ReturnDue<T>>::remove(listing_id);
+        // Subscriptions to the listing are removed when their next payment comes due
+        <SubscriptionPeriods<T>>::remove(listing_id);
+        // Reports that were never resolved weren't frivolous
+        for report in <Reports<T>>::take(listing_id) {
+            T::Currency::unreserve(&report.reporter, report.bond);
+        }
+    }
+
+    /// Dissolve the bundle containing a listing, if there is one.
+    fn dissolve_bundle_of(listing_id: ListingId) {
+        if let Some(bundle_id) = ListingBundles::get(listing_id) {
+            if let Some(bundle) = <Bundles<T>>::get(bundle_id) {
+                Self::remove_bundle(bundle_id, bundle);
+            }
+        }
+    }
+
+    /// Remove a bundle, leaving its listings for sale individually.
+    fn remove_bundle(bundle_id: BundleId, bundle: BundleOf<T>) {
+        for listing_id in bundle.listings.iter() {
+            ListingBundles::remove(listing_id);
+        }
+        <Bundles<T>>::remove(bundle_id);
+        Self::deposit_event(RawEvent::BundleDissolved(bundle_id));
+    }
+
+    /// Append an active listing to its category's index.
+    fn index_category(listing_id: ListingId, category: CategoryId) {
+        let position = CategorySizes::get(category);
+        CategoryListings::insert(category, position, listing_id);
+        CategorySizes::insert(category, position + 1);
+        CategoryPositions::insert(listing_id, (category, position));
+    }
+
+    /// Remove a listing from its category's index, if it is in one. The last
+    /// listing in the category is swapped into the vacated position.
+    fn unindex_category(listing_id: ListingId) {
+        if let Some((category, position)) = CategoryPositions::take(listing_id) {
+            let last = CategorySizes::get(category) - 1;
+            if position != last {
+                let moved = CategoryListings::get(category, last);
+                CategoryListings::insert(category, position, moved);
+                CategoryPositions::insert(moved, (category, position));
+            }
+            CategoryListings::remove(category, last);
+            CategorySizes::insert(category, last);
+        }
+    }
+
+    /// Append a listing to its seller's index.
+    fn index_seller(listing_id: ListingId, seller: &T::AccountId) {
+        let position = <SellerListingCounts<T>>::get(seller);
+        <SellerListings<T>>::insert(seller, position, listing_id);
+        <SellerListingCounts<T>>::insert(seller, position + 1);
+        SellerPositions::insert(listing_id, position);
+    }
+
+    /// Remove a listing from its seller's index, swapping the seller's last listing into its place.
+    fn unindex_seller(listing_id: ListingId, seller: &T::AccountId) {
+        let position = SellerPositions::take(listing_id);
+        let last = <SellerListingCounts<T>>::get(seller) - 1;
+        if position != last {
+            let moved = <SellerListings<T>>::get(seller, last);
+            <SellerListings<T>>::insert(seller, position, moved);
+            SellerPositions::insert(moved, position);
+        }
+        <SellerListings<T>>::remove(seller, last);
+        <SellerListingCounts<T>>::insert(seller, last);
+    }
+
+    /// Append a listing to its buyer's index.
+    fn index_buyer(listing_id: ListingId, buyer: &T::AccountId) {
+        let position = <BuyerListingCounts<T>>::get(buyer);
+        <BuyerListings<T>>::insert(buyer, position, listing_id);
+        <BuyerListingCounts<T>>::insert(buyer, position + 1);
+        BuyerPositions::insert(listing_id, position);
+    }
+
+    /// Remove a listing from its buyer's index, swapping the buyer's last purchase into its place.
+    fn unindex_buyer(listing_id: ListingId, buyer: &T::AccountId) {
+        let position = BuyerPositions::take(listing_id);
+        let last = <BuyerListingCounts<T>>::get(buyer) - 1;
+        if position != last {
+            let moved = <BuyerListings<T>>::get(buyer, last);
+            <BuyerListings<T>>::insert(buyer, position, moved);
+            BuyerPositions::insert(moved, position);
+        }
+        <BuyerListings<T>>::remove(buyer, last);
+        <BuyerListingCounts<T>>::insert(buyer, last);
+    }
+
+    /// Convert listings stored in the baseline layout to the current one in a single pass,
+    /// and build the indexes and records they were posted without. They are priced in the
+    /// default asset, in the default category, with no tags. No deposit was reserved for
+    /// them, so none is returned when they are cancelled or settled.
+    /// Migrates storage from version 0 to 1.
+    fn migrate_baseline_listings() {
+        let category = CategoryId::default();
+        if !Categories::exists(category) {
+            Categories::insert(category, 0);
+        }
+
+        for listing_id in 0..NextId::get() {
+            let old = match Self::legacy_value::<BaselineListing<T::AccountId>>(b"Marketplace Listings", &listing_id) {
+                Some(old) => old,
+                None => continue,
+            };
+            let buyer = <Buyers<T>>::get(listing_id);
+
+            // Every listing was posted with a status, but don't leave one without
+            if Statuses::get(listing_id) == Status::NonExistant {
+                Statuses::insert(listing_id, if buyer.is_some() { Status::Sold } else { Status::Active });
+            }
+            if Statuses::get(listing_id) == Status::Active {
+                Self::index_category(listing_id, category);
+            }
+            Self::index_seller(listing_id, &old.seller);
+            if let Some(buyer) = buyer {
+                Self::index_buyer(listing_id, &buyer);
+            }
+            <Deposits<T>>::insert(listing_id, T::Balance::zero());
+            CategoryListingCounts::mutate(category, |c| *c += 1);
+
+            <Listings<T>>::insert(listing_id, ListingOf::<T> {
+                seller: old.seller,
+                price: old.price.into(),
+                asset: Default::default(),
+                description: old.description,
+                category,
+                tags: Vec::new(),
+                version: 0,
+            });
+        }
+    }
+
+    /// Read a value from a storage map using a layout the map no longer has.
+    fn legacy_value<V: Decode>(map_prefix: &[u8], key: &impl Encode) -> Option<V> {
+        let mut raw_key = map_prefix.to_vec();
+        key.encode_to(&mut raw_key);
+        support::storage::hashed::get(&runtime_io::blake2_256, &raw_key)
+    }
+
+    /// Up to `count` listings posted by `seller`, starting from position `start`.
+    pub fn listings_by_seller(seller: T::AccountId, start: u32, count: u32) -> Vec<ListingId> {
+        let end = <SellerListingCounts<T>>::get(&seller).min(start.saturating_add(count));
+        (start..end).map(|position| <SellerListings<T>>::get(&seller, position)).collect()
+    }
+
+    /// Up to `count` listings bought by `buyer`, starting from position `start`.
+    pub fn listings_by_buyer(buyer: T::AccountId, start: u32, count: u32) -> Vec<ListingId> {
+        let end = <BuyerListingCounts<T>>::get(&buyer).min(start.saturating_add(count));
+        (start..end).map(|position| <BuyerListings<T>>::get(&buyer, position)).collect()
+    }
+
+    /// Up to `count` settled sales `who` took part in, oldest first, starting from position `start`.
+    pub fn trade_history(who: T::AccountId, start: u32, count: u32) -> Vec<(ListingId, SaleOf<T>)> {
+        let end = <TradeCounts<T>>::get(&who).min(start.saturating_add(count));
+        (start..end)
+            .map(|position| <Trades<T>>::get(&who, position))
+            .filter_map(|id| <Sales<T>>::get(id).map(|sale| (id, sale)))
+            .collect()
+    }
+
+    /// Up to `count` active listings in `category`, starting from position `start`,
+    /// and whether each seller is verified.
+    pub fn listings_in_category(category: CategoryId, start: u32, count: u32)
+        -> Vec<(ListingId, ListingOf<T>, bool)>
+    {
+        let end = CategorySizes::get(category).min(start.saturating_add(count));
+        (start..end)
+            .map(|position| CategoryListings::get(category, position))
+            .filter_map(|id| <Listings<T>>::get(id).map(|listing| {
+                let verified = T::Verification::is_verified(&listing.seller);
+                (id, listing, verified)
+            }))
+            .collect()
+    }
+}
+
+/// Storage versions:
+/// 0. The original layout. Listings have only a seller, a `u32` price and a description,
+///    and there are no deposits or indexes.
+/// 1. Listings have an asset, a category, tags, a version and a `Balance` price. Each has
+///    a deposit, and is indexed by category, seller and buyer.
+const CURRENT_STORAGE_VERSION: u32 = 1;
+
+impl<T: Trait> VersionedStorage for Module<T> {
+    const CURRENT_STORAGE_VERSION: u32 = CURRENT_STORAGE_VERSION;
+
+    fn storage_version() -> u32 {
+        StorageVersion::get()
+    }
+
+    fn set_storage_version(version: u32) {
+        StorageVersion::put(version);
+    }
+
+    fn migrate_step(from: u32) -> u32 {
+        match from {
+            0 => {
+                Self::migrate_baseline_listings();
+                1
+            },
+            _ => from + 1,
+        }
+    }
+}
+
+client::decl_runtime_apis! {
+    /// Runtime API for browsing the marketplace without scanning every listing.
+    #[api_version(4)]
+    pub trait MarketplaceApi<AccountId, AssetId, Balance, BlockNumber, Feedback> where
+        AccountId: Codec,
+        AssetId: Codec,
+        Balance: Codec,
+        BlockNumber: Codec,
+        Feedback: Codec,
+    {
+        /// Up to `count` active listings in `category`, starting from position `start`,
+        /// and whether each seller is verified.
+        fn listings_in_category(category: CategoryId, start: u32, count: u32)
+            -> Vec<(ListingId, Listing<AccountId, AssetId, Balance>, bool)>;
+
+        /// Up to `count` listings posted by `seller`, starting from position `start`.
+        fn listings_by_seller(seller: AccountId, start: u32, count: u32) -> Vec<ListingId>;
+
+        /// Up to `count` listings bought by `buyer`, starting from position `start`.
+        fn listings_by_buyer(buyer: AccountId, start: u32, count: u32) -> Vec<ListingId>;
+
+        /// Up to `count` settled sales `who` took part in, oldest first, starting from position `start`.
+        fn trade_history(who: AccountId, start: u32, count: u32)
+            -> Vec<(ListingId, Sale<AccountId, AssetId, Balance, BlockNumber, Feedback>)>;
+
+        /// Whether `seller` has bonded enough collateral for all their listings.
+        fn is_bonded(seller: AccountId) -> bool;
+    }
+}
+
+/// Lets an insurance pool make up what sellers' collateral didn't cover.
+impl<T: Trait> CompensationShortfall<T::AccountId, AssetIdOf<T>, T::Balance> for Module<T> {
+    fn shortfall(listing_id: ListingId) -> Option<(T::AccountId, AssetIdOf<T>, T::Balance)> {
+        <Shortfalls<T>>::get(listing_id)
+    }
+
+    fn reduce(listing_id: ListingId, amount: T::Balance) {
+        <Shortfalls<T>>::mutate(listing_id, |shortfall| {
+            if let Some((_, _, unpaid)) = shortfall {
+                *unpaid = unpaid.saturating_sub(amount);
+            }
+        });
+    }
+
+    fn settle(listing_id: ListingId) {
+        <Shortfalls<T>>::remove(listing_id);
+    }
+}
+
+decl_event!(
+    pub enum Event<T> where
+        AccountId = <T as system::Trait>::AccountId,
+        Balance = <T as Trait>::Balance,
+        AssetId = AssetIdOf<T>,
+        BlockNumber = <T as system::Trait>::BlockNumber
+    {
+        // Seller, listing id, listing, whether the seller is verified
+        Posted(AccountId, ListingId, Listing<AccountId, AssetId, Balance>, bool),
+        Cancelled(ListingId),
+        Sold(AccountId, ListingId),
+        Settled(AccountId, ListingId),
+        // Listing was forcibly removed for a reason, and this much of its deposit slashed
+        Removed(ListingId, ReasonCode, Balance),
+        // Reporter, listing id, reason
+        Reported(AccountId, ListingId, ReasonCode),
+        // Listing was hidden pending moderation
+        Flagged(ListingId),
+        // Listing id, whether the reports were upheld
+        ReportsResolved(ListingId, bool),
+        // Buyer, listing id
+        ReturnRequested(AccountId, ListingId),
+        Returned(ListingId),
+        ReturnRejected(ListingId),
+        // Seller, asset, amount
+        CollateralBonded(AccountId, AssetId, Balance),
+        CollateralUnbonded(AccountId, AssetId, Balance),
+        // Buyer, listing id, amount paid from the seller's collateral
+        BuyerCompensated(AccountId, ListingId, Balance),
+        // Seller, asset, sale fee the insurance pool couldn't accept, which the seller kept instead
+        SaleFeeNotPaid(AccountId, AssetId, Balance),
+        // Account, number of blocks it is suspended for
+        Suspended(AccountId, BlockNumber),
+        // Listing id, old price, new price, old description, new description
+        Updated(ListingId, Balance, Balance, u32, u32),
+        // Category id, name
+        CategoryAdded(CategoryId, u32),
+        CategoryRemoved(CategoryId),
+        // Category id, whether only verified sellers may post in it
+        CategoryVerifiedOnly(CategoryId, bool),
+        // Seller, bundle id, member listings, bundle price
+        BundleCreated(AccountId, BundleId, Vec<ListingId>, Balance),
+        BundleDissolved(BundleId),
+        // Buyer, bundle id. A `Sold` event is also emitted for every member listing.
+        BundleSold(AccountId, BundleId),
+        // Reviewer, listing id
+        ReviewCommitted(AccountId, ListingId),
+        ReviewRevealed(AccountId, ListingId),
+        // Reviewee, listing id
+        Rebutted(AccountId, ListingId),
+        // Revealed feedback has been given to the reputation system
+        BlindReviewClosed(ListingId),
+        // Listing id, milestone index, amount paid to the seller
+        MilestoneApproved(ListingId, u32, Balance),
+        // Buyer, listing id, milestone index
+        MilestoneDisputed(AccountId, ListingId, u32),
+        // Listing id, milestone index, amount paid to the seller, amount refunded to the buyer
+        MilestoneResolved(ListingId, u32, Balance, Balance),
+        // Every milestone of the service is settled, and it can be reviewed
+        ServiceCompleted(ListingId),
+        // Renter, listing id, block the item must be returned by
+        Rented(AccountId, ListingId, BlockNumber),
+        RentalOverdue(ListingId),
+        RentalReturned(ListingId),
+        // Listing id, deposit paid to the seller
+        RentalDepositClaimed(ListingId, Balance),
+        // Subscriber, listing id
+        Subscribed(AccountId, ListingId),
+        // Subscriber, listing id, amount paid
+        SubscriptionPaid(AccountId, ListingId, Balance),
+        // A payment failed, e.g. because the allowance ran out. Subscriber, listing id
+        SubscriptionPaused(AccountId, ListingId),
+        SubscriptionResumed(AccountId, ListingId),
+        SubscriptionCancelled(AccountId, ListingId),
+    }
+);
+
+/// tests for this module
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets;
+
+    use runtime_io::with_externalities;
+    use primitives::{H256, Blake2Hasher};
+    use support::{impl_outer_origin, assert_ok, assert_noop, parameter_types};
+    use sr_primitives::{
+        Perbill,
+        traits::{BlakeTwo256, IdentityLookup, ConvertInto},
+        testing::Header,
+    };
+    use sr_primitives::traits::{OnInitialize, OnFinalize};
+    use support::traits::Currency;
+    use std::cell::RefCell;
+
+    impl_outer_origin! {
+        pub enum Origin for Test {}
+    }
+
+    // For testing the module, we construct most of a mock runtime. This means
+    // first constructing a configuration type (`Test`) which `impl`s each of the
+    // configuration traits of modules we want to use.
+    #[derive(Clone, Eq, PartialEq)]
+    pub struct Test;
+    parameter_types! {
+        pub const BlockHashCount: u64 = 250;
+        pub const MaximumBlockWeight: u32 = 1024;
+        pub const MaximumBlockLength: u32 = 2 * 1024;
+        pub const AvailableBlockRatio: Perbill = Perbill::one();
+    }
+    impl system::Trait for Test {
+        type Origin = Origin;
+        type Call = ();
+        type Index = u64;
+        type BlockNumber = u64;
+        type Hash = H256;
+        type Hashing = BlakeTwo256;
+        type AccountId = u64;
+        type Lookup = IdentityLookup<Self::AccountId>;
+        type Header = Header;
+        type WeightMultiplierUpdate = ();
+        type Event = ();
+        type BlockHashCount = BlockHashCount;
+        type MaximumBlockWeight = MaximumBlockWeight;
+        type MaximumBlockLength = MaximumBlockLength;
+        type AvailableBlockRatio = AvailableBlockRatio;
+        type Version = ();
+    }
+
+    parameter_types! {
+        pub const ExistentialDeposit: u64 = 0;
+        pub const TransferFee: u64 = 0;
+        pub const CreationFee: u64 = 0;
+        pub const TransactionBaseFee: u64 = 0;
+        pub const TransactionByteFee: u64 = 0;
+    }
+    impl balances::Trait for Test {
+        type Balance = u64;
+        type OnFreeBalanceZero = ();
+        type OnNewAccount = ();
+        type Event = ();
+        type TransactionPayment = ();
+        type DustRemoval = ();
+        type TransferPayment = ();
+        type ExistentialDeposit = ExistentialDeposit;
+        type TransferFee = TransferFee;
+        type CreationFee = CreationFee;
+        type TransactionBaseFee = TransactionBaseFee;
+        type TransactionByteFee = TransactionByteFee;
+        type WeightToFee = ConvertInto;
+    }
+
+    impl Reputation<u64> for () {
+        type Score = ();
+        type Feedback = ();
+        fn rate(_rater: u64, _ratee: u64, _feedback: Self::Feedback)
+          -> Result {
+              Ok(())
+        }
+        fn reputation(_who : u64) -> Self::Score {
+            ()
+        }
+    }
+    impl ContextualReputation<u64, ListingId> for () {}
+
+    // Only account 1 is verified.
+    pub struct VerifiedAccounts;
+    impl SellerVerification<u64> for VerifiedAccounts {
+        fn is_verified(who: &u64) -> bool {
+            *who == 1
+        }
+    }
+
+    thread_local! {
+        static SALE_FEE: RefCell<Perbill> = RefCell::new(Perbill::zero());
+    }
+    // No fee is taken unless a test sets one.
+    pub struct SaleFee;
+    impl Get<Perbill> for SaleFee {
+        fn get() -> Perbill {
+            SALE_FEE.with(|v| *v.borrow())
+        }
+    }
+
+    parameter_types! {
+        pub const InsurancePool: u64 = 99;
+        pub const ListingDeposit: u64 = 10;
+        pub const MaxTags: u32 = 2;
+        pub const MaxCartSize: u32 = 3;
+        pub const MaxMilestones: u32 = 3;
+        pub const MaxRentalPeriod: u32 = 10;
+        pub const MaxPaymentsPerBlock: u32 = 2;
+        pub const BlindReviewPeriod: u64 = 5;
+        pub const MaxCommentLength: u32 = 4;
+        pub const ReturnWindow: u64 = 10;
+        pub const CollateralRatio: Perbill = Perbill::from_percent(50);
+        pub const ReportBond: u64 = 5;
+        pub const ReportThreshold: u32 = 2;
+        pub const ReporterReward: Perbill = Perbill::from_percent(50);
+    }
+    impl assets::Trait for Test {
+        type Currency = Balances;
+        type Event = ();
+    }
+
+    impl Trait for Test {
+        type ReputationSystem = ();
+        type Balance = u64;
+        type Currency = Balances;
+        type Assets = Assets;
+        type ListingDeposit = ListingDeposit;
+        type SaleFee = SaleFee;
+        type InsurancePool = InsurancePool;
+        type Verification = VerifiedAccounts;
+        type CategoryOrigin = system::EnsureRoot<u64>;
+        type ModeratorOrigin = system::EnsureRoot<u64>;
+        type ReportBond = ReportBond;
+        type ReportThreshold = ReportThreshold;
+        type ReporterReward = ReporterReward;
+        type ReturnWindow = ReturnWindow;
+        type CollateralRatio = CollateralRatio;
+        type MaxTags = MaxTags;
+        type MaxCartSize = MaxCartSize;
+        type MaxMilestones = MaxMilestones;
+        type MaxRentalPeriod = MaxRentalPeriod;
+        type MaxPaymentsPerBlock = MaxPaymentsPerBlock;
+        type BlindReviewPeriod = BlindReviewPeriod;
+        type MaxCommentLength = MaxCommentLength;
+        type Event = ();
+    }
+    type Balances = balances::Module<Test>;
+    type Assets = assets::Module<Test>;
+    type Marketplace = Module<Test>;
+
+    // This function basically just builds a genesis storage key/value store according to
+    // our desired mockup. Accounts 1 and 2 start with 100 units, account 3 with 5.
+    // Categories 0 and 1 exist.
+    fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+        let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+        balances::GenesisConfig::<Test> {
+            balances: vec![(1, 100), (2, 100), (3, 5)],
+            vesting: vec![],
+        }.assimilate_storage(&mut t).unwrap();
+        GenesisConfig {
+            categories: vec![(0, 0), (1, 1)],
+        }.assimilate_storage(&mut t).unwrap();
+        t.into()
+    }
+
+    #[test]
+    fn listing_id_increments_correctly() {
+        with_externalities(&mut new_test_ext(), || {
+
+            // Post a listing
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 123, 456, 0, vec![], 0));
+            // asserting that the stored value is equal to what we stored
+            //assert_eq!(TemplateModule::something(), Some(42));
             assert_eq!(Marketplace::next_id(),1);
         });
     }
+
+    #[test]
+    fn deposit_reserved_and_returned_on_cancel() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 123, 456, 0, vec![], 0));
+            assert_eq!(Balances::reserved_balance(1), 10);
+            assert_eq!(Marketplace::deposit(0), 10);
+
+            assert_ok!(Marketplace::cancel_listing(Origin::signed(1), 0));
+            assert_eq!(Balances::reserved_balance(1), 0);
+            assert_eq!(Balances::free_balance(1), 100);
+        });
+    }
+
+    #[test]
+    fn deposit_returned_on_settlement() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 50, 0));
+            assert_ok!(Marketplace::review(Origin::signed(1), 0, (), vec![]));
+            assert_ok!(Marketplace::review(Origin::signed(2), 0, (), vec![]));
+
+            assert_eq!(Balances::reserved_balance(1), 0);
+            assert_eq!(Balances::free_balance(1), 150);
+        });
+    }
+
+    #[test]
+    fn sale_fee_paid_into_insurance_pool() {
+        with_externalities(&mut new_test_ext(), || {
+            SALE_FEE.with(|v| *v.borrow_mut() = Perbill::from_percent(10));
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 50, 0));
+            assert_ok!(Marketplace::review(Origin::signed(1), 0, (), vec![]));
+            assert_ok!(Marketplace::review(Origin::signed(2), 0, (), vec![]));
+
+            // The pool's account doesn't exist yet, so the seller keeps the fee
+            assert_eq!(Balances::free_balance(1), 150);
+
+            Balances::make_free_balance_be(&99, 1);
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 1, 50, 0));
+            assert_ok!(Marketplace::review(Origin::signed(1), 1, (), vec![]));
+            assert_ok!(Marketplace::review(Origin::signed(2), 1, (), vec![]));
+
+            assert_eq!(Balances::free_balance(1), 195);
+            assert_eq!(Balances::free_balance(99), 6);
+            // The archived price is what the buyer paid
+            assert_eq!(Marketplace::sale(1).unwrap().price, 50);
+        });
+    }
+
+    #[test]
+    fn deposit_slashed_on_forced_removal() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 123, 456, 0, vec![], 0));
+            assert_noop!(Marketplace::force_remove_listing(Origin::signed(2), 0, 1, true), "Invalid origin");
+            assert_ok!(Marketplace::force_remove_listing(Origin::ROOT, 0, 1, true));
+
+            assert_eq!(Balances::reserved_balance(1), 0);
+            assert_eq!(Balances::free_balance(1), 90);
+            assert!(!<Listings<Test>>::exists(0));
+        });
+    }
+
+    #[test]
+    fn cannot_post_without_deposit() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_noop!(
+                Marketplace::post_listing(Origin::signed(3), 123, 456, 0, vec![], 0),
+                "Not enough free balance to reserve the listing deposit"
+            );
+        });
+    }
+
+    #[test]
+    fn post_listing_checks_category_and_tags() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_noop!(
+                Marketplace::post_listing(Origin::signed(1), 123, 456, 7, vec![], 0),
+                "No such category"
+            );
+            assert_noop!(
+                Marketplace::post_listing(Origin::signed(1), 123, 456, 0, vec![1, 2, 3], 0),
+                "Too many tags"
+            );
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 123, 456, 0, vec![1, 2], 0));
+        });
+    }
+
+    #[test]
+    fn category_index_tracks_active_listings() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 10, 0, 0, vec![], 0));
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 11, 1, 1, vec![], 0));
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 12, 2, 0, vec![], 0));
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 13, 3, 0, vec![], 0));
+
+            let ids = |c, start, count| Marketplace::listings_in_category(c, start, count)
+                .into_iter().map(|(id, _, _)| id).collect::<Vec<_>>();
+            assert_eq!(ids(0, 0, 10), vec![0, 2, 3]);
+            assert_eq!(ids(0, 1, 1), vec![2]);
+            assert_eq!(ids(1, 0, 10), vec![1]);
+
+            // Selling and cancelling both take listings out of the index
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 10, 0));
+            assert_eq!(ids(0, 0, 10), vec![3, 2]);
+            assert_ok!(Marketplace::cancel_listing(Origin::signed(1), 2));
+            assert_eq!(ids(0, 0, 10), vec![3]);
+            assert_eq!(Marketplace::category_size(0), 1);
+        });
+    }
+
+    #[test]
+    fn categories_are_governed() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_noop!(Marketplace::add_category(Origin::signed(1), 2, 2), "Invalid origin");
+            assert_ok!(Marketplace::add_category(Origin::ROOT, 2, 2));
+            assert_eq!(Marketplace::category(2), Some(2));
+
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 10, 0, 2, vec![], 0));
+            assert_noop!(Marketplace::remove_category(Origin::ROOT, 2), "Category still has listings");
+            assert_ok!(Marketplace::cancel_listing(Origin::signed(1), 0));
+            assert_ok!(Marketplace::remove_category(Origin::ROOT, 2));
+
+            // Sold listings may still go back on sale in their category
+            assert_ok!(Marketplace::add_category(Origin::ROOT, 2, 2));
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 10, 0, 2, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 1, 10, 0));
+            assert_eq!(Marketplace::category_size(2), 0);
+            assert_noop!(Marketplace::remove_category(Origin::ROOT, 2), "Category still has listings");
+            assert_ok!(Marketplace::review(Origin::signed(1), 1, (), vec![]));
+            assert_ok!(Marketplace::review(Origin::signed(2), 1, (), vec![]));
+            assert_ok!(Marketplace::remove_category(Origin::ROOT, 2));
+        });
+    }
+
+    #[test]
+    fn participant_indexes_follow_listing_lifecycle() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 10, 0, 0, vec![], 0));
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 11, 1, 0, vec![], 0));
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 12, 2, 0, vec![], 0));
+            assert_eq!(Marketplace::listings_by_seller(1, 0, 10), vec![0, 1, 2]);
+
+            assert_ok!(Marketplace::buy(Origin::signed(2), 1, 11, 0));
+            assert_eq!(Marketplace::listings_by_buyer(2, 0, 10), vec![1]);
+
+            assert_ok!(Marketplace::cancel_listing(Origin::signed(1), 0));
+            assert_eq!(Marketplace::listings_by_seller(1, 0, 10), vec![2, 1]);
+
+            assert_ok!(Marketplace::review(Origin::signed(1), 1, (), vec![]));
+            assert_ok!(Marketplace::review(Origin::signed(2), 1, (), vec![]));
+            assert_eq!(Marketplace::listings_by_seller(1, 0, 10), vec![2]);
+            assert_eq!(Marketplace::buyer_listing_count(2), 0);
+        });
+    }
+
+
+    #[test]
+    fn seller_can_update_active_listing() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 10, 0, 0, vec![], 0));
+            assert_noop!(
+                Marketplace::update_listing(Origin::signed(2), 0, 20, 1),
+                "Cannot update another seller's listing"
+            );
+            assert_ok!(Marketplace::update_listing(Origin::signed(1), 0, 20, 1));
+            let listing = Marketplace::listing(0).unwrap();
+            assert_eq!((listing.price, listing.description), (20, 1));
+
+            assert_eq!(listing.version, 1);
+
+            // A buyer who saw the old listing doesn't get the new terms
+            assert_noop!(Marketplace::buy(Origin::signed(2), 0, 10, 1), "Listing price exceeds max price");
+            assert_noop!(Marketplace::buy(Origin::signed(2), 0, 20, 0), "Listing has been updated");
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 25, 1));
+            assert_noop!(
+                Marketplace::update_listing(Origin::signed(1), 0, 30, 1),
+                "Cannot update already-sold listing"
+            );
+        });
+    }
+
+    #[test]
+    fn buy_many_is_all_or_nothing() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 10, 0, 0, vec![], 0));
+            assert_ok!(Marketplace::post_listing(Origin::signed(2), 11, 1, 0, vec![], 0));
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 12, 2, 0, vec![], 0));
+
+            // Listing 1 is the caller's own, so nothing is bought
+            assert_noop!(
+                Marketplace::buy_many(Origin::signed(2), vec![(0, 10, 0), (1, 11, 0)]),
+                "Can't buy own listing"
+            );
+            assert_noop!(
+                Marketplace::buy_many(Origin::signed(2), vec![(0, 10, 0), (0, 10, 0)]),
+                "Listing appears more than once"
+            );
+            assert_eq!(Marketplace::status(0), Status::Active);
+
+            assert_ok!(Marketplace::buy_many(Origin::signed(2), vec![(0, 10, 0), (2, 12, 0)]));
+            assert_eq!(Marketplace::status(0), Status::Sold);
+            assert_eq!(Marketplace::status(2), Status::Sold);
+            assert_eq!(Marketplace::listings_by_buyer(2, 0, 10), vec![0, 2]);
+
+            // Each sale settles on its own
+            assert_ok!(Marketplace::review(Origin::signed(1), 0, (), vec![]));
+            assert_ok!(Marketplace::review(Origin::signed(2), 0, (), vec![]));
+            assert_eq!(Marketplace::status(2), Status::Sold);
+        });
+    }
+
+    #[test]
+    fn bundle_is_bought_in_one_call() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 10, 0, 0, vec![], 0));
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 20, 1, 0, vec![], 0));
+            assert_noop!(
+                Marketplace::create_bundle(Origin::signed(1), vec![0, 1], 30),
+                "Bundle price must be less than the listings' combined price"
+            );
+            assert_ok!(Marketplace::create_bundle(Origin::signed(1), vec![0, 1], 25));
+            assert_eq!(Marketplace::listing_bundle(1), Some(0));
+
+            assert_noop!(Marketplace::buy_bundle(Origin::signed(2), 0, 24), "Bundle price exceeds max price");
+            assert_ok!(Marketplace::buy_bundle(Origin::signed(2), 0, 25));
+            assert_eq!(Marketplace::status(0), Status::Sold);
+            assert_eq!(Marketplace::status(1), Status::Sold);
+            assert_eq!(Marketplace::buyer(1), Some(2));
+            assert_eq!(Marketplace::bundle(0), None);
+            assert_eq!(Marketplace::listing_bundle(0), None);
+        });
+    }
+
+    #[test]
+    fn buying_a_member_dissolves_the_bundle() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 10, 0, 0, vec![], 0));
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 20, 1, 0, vec![], 0));
+            assert_ok!(Marketplace::create_bundle(Origin::signed(1), vec![0, 1], 25));
+
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 10, 0));
+            assert_eq!(Marketplace::bundle(0), None);
+            assert_eq!(Marketplace::listing_bundle(1), None);
+            assert_noop!(Marketplace::buy_bundle(Origin::signed(2), 0, 25), "No such bundle");
+            assert_eq!(Marketplace::status(1), Status::Active);
+        });
+    }
+
+    #[test]
+    fn payment_is_escrowed_until_settlement() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 30, 0, 0, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 30, 0));
+            assert_eq!(Balances::reserved_balance(2), 30);
+            assert_eq!(Marketplace::escrow(0), 30);
+
+            assert_ok!(Marketplace::review(Origin::signed(2), 0, (), vec![]));
+            assert_eq!(Balances::free_balance(1), 90);
+            assert_ok!(Marketplace::review(Origin::signed(1), 0, (), vec![]));
+            assert_eq!(Balances::reserved_balance(2), 0);
+            assert_eq!(Balances::free_balance(2), 70);
+            assert_eq!(Balances::free_balance(1), 130);
+        });
+    }
+
+    #[test]
+    fn listings_can_be_priced_in_issued_assets() {
+        with_externalities(&mut new_test_ext(), || {
+            // Account 2 issues an asset and gives some to account 3
+            assert_ok!(Assets::issue(Origin::signed(2), 1000));
+            assert_ok!(Assets::transfer(Origin::signed(2), 1, 3, 40));
+
+            assert_noop!(Marketplace::post_listing(Origin::signed(1), 30, 0, 0, vec![], 2), "No such asset");
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 30, 0, 0, vec![], 1));
+            assert_ok!(Marketplace::buy(Origin::signed(3), 0, 30, 0));
+            assert_eq!(Assets::reserved(1, 3), 30);
+            // Native balance is untouched
+            assert_eq!(Balances::free_balance(3), 5);
+
+            assert_ok!(Marketplace::review(Origin::signed(1), 0, (), vec![]));
+            assert_ok!(Marketplace::review(Origin::signed(3), 0, (), vec![]));
+            assert_eq!(Assets::balance(1, 1), 30);
+            assert_eq!(Assets::balance(1, 3), 10);
+        });
+    }
+
+    #[test]
+    fn buy_many_checks_combined_balance() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 60, 0, 0, vec![], 0));
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 1, 0, vec![], 0));
+            assert_noop!(
+                Marketplace::buy_many(Origin::signed(2), vec![(0, 60, 0), (1, 50, 0)]),
+                "Insufficient balance to buy every listing"
+            );
+        });
+    }
+
+    #[test]
+    fn buy_many_undoes_reservations_when_one_fails() {
+        with_externalities(&mut new_test_ext(), || {
+            // Account 3 can afford the listing in the issued asset, but not the native one
+            assert_ok!(Assets::issue(Origin::signed(2), 1000));
+            assert_ok!(Assets::transfer(Origin::signed(2), 1, 3, 40));
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 30, 0, 0, vec![], 1));
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 10, 1, 0, vec![], 0));
+
+            assert_eq!(
+                Marketplace::buy_many(Origin::signed(3), vec![(0, 30, 0), (1, 10, 0)]),
+                Err("Insufficient balance to buy every listing")
+            );
+            assert_eq!(Assets::reserved(1, 3), 0);
+            assert_eq!(Assets::balance(1, 3), 40);
+            assert_eq!(Marketplace::status(0), Status::Active);
+            assert_eq!(Marketplace::buyer(0), None);
+        });
+    }
+
+    #[test]
+    fn bundle_price_is_split_between_member_sales() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 10, 0, 0, vec![], 0));
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 30, 1, 0, vec![], 0));
+            assert_ok!(Marketplace::create_bundle(Origin::signed(1), vec![0, 1], 21));
+            assert_ok!(Marketplace::buy_bundle(Origin::signed(2), 0, 21));
+
+            assert_eq!(Marketplace::escrow(0), 5);
+            assert_eq!(Marketplace::escrow(1), 16);
+            assert_eq!(Balances::reserved_balance(2), 21);
+        });
+    }
+
+    // Write a listing with the layout of chains that predate categories and deposits.
+    fn put_baseline_listing(listing_id: ListingId, seller: u64, price: u32) {
+        let mut key = b"Marketplace Listings".to_vec();
+        listing_id.encode_to(&mut key);
+        let raw = (seller, price, 3u32).encode();
+        support::storage::unhashed::put_raw(&runtime_io::blake2_256(&key), &raw);
+    }
+
+    #[test]
+    fn new_chains_start_at_current_storage_version() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_eq!(Marketplace::storage_version(), CURRENT_STORAGE_VERSION);
+        });
+    }
+
+    #[test]
+    fn baseline_storage_is_migrated() {
+        with_externalities(&mut new_test_ext(), || {
+            // An active listing, a sold listing, and one that somehow lost its status
+            StorageVersion::kill();
+            put_baseline_listing(0, 1, 10);
+            Statuses::insert(0, Status::Active);
+            put_baseline_listing(1, 1, 20);
+            Statuses::insert(1, Status::Sold);
+            <Buyers<Test>>::insert(1, 2);
+            put_baseline_listing(2, 2, 5);
+            NextId::put(3);
+
+            Marketplace::on_initialize(1);
+
+            assert_eq!(Marketplace::storage_version(), 1);
+            assert_eq!(Marketplace::listing(0), Some(Listing {
+                seller: 1,
+                price: 10u64,
+                asset: 0,
+                description: 3,
+                category: 0,
+                tags: vec![],
+                version: 0,
+            }));
+            assert_eq!(Marketplace::listing(1).unwrap().price, 20u64);
+            assert_eq!(Marketplace::listing(2).unwrap().seller, 2);
+            assert_eq!(Marketplace::status(1), Status::Sold);
+            assert_eq!(Marketplace::status(2), Status::Active);
+
+            assert_eq!(Marketplace::listings_by_seller(1, 0, 10), vec![0, 1]);
+            assert_eq!(Marketplace::listings_by_seller(2, 0, 10), vec![2]);
+            assert_eq!(Marketplace::listings_by_buyer(2, 0, 10), vec![1]);
+            let in_category = Marketplace::listings_in_category(0, 0, 10).into_iter()
+                .map(|(id, _, _)| id)
+                .collect::<Vec<_>>();
+            assert_eq!(in_category, vec![0, 2]);
+
+            // No deposit was taken, so cancelling returns none
+            assert_eq!(Marketplace::deposit(0), 0);
+            assert_ok!(Marketplace::cancel_listing(Origin::signed(1), 0));
+            assert_eq!(Balances::free_balance(1), 100);
+        });
+    }
+
+    fn commitment(salt: u8) -> H256 {
+        <Test as system::Trait>::Hashing::hash_of(&((), H256::repeat_byte(salt)))
+    }
+
+    #[test]
+    fn blind_reviews_are_revealed_after_both_commit() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 50, 0));
+
+            assert_ok!(Marketplace::commit_review(Origin::signed(1), 0, commitment(1)));
+            assert_noop!(Marketplace::review(Origin::signed(2), 0, (), vec![]), "Reviews of this listing are blind");
+            assert_noop!(
+                Marketplace::reveal_review(Origin::signed(1), 0, (), vec![], H256::repeat_byte(1)),
+                "Both parties must commit before revealing"
+            );
+
+            // The second commitment settles the sale
+            assert_ok!(Marketplace::commit_review(Origin::signed(2), 0, commitment(2)));
+            assert_eq!(Balances::free_balance(1), 150);
+            assert!(!<Listings<Test>>::exists(0));
+
+            assert_noop!(
+                Marketplace::reveal_review(Origin::signed(1), 0, (), vec![], H256::repeat_byte(2)),
+                "Feedback does not match commitment"
+            );
+            assert_ok!(Marketplace::reveal_review(Origin::signed(1), 0, (), vec![], H256::repeat_byte(1)));
+            assert!(Marketplace::blind_review(0).is_some());
+            assert_ok!(Marketplace::reveal_review(Origin::signed(2), 0, (), vec![], H256::repeat_byte(2)));
+            assert!(Marketplace::blind_review(0).is_none());
+        });
+    }
+
+    #[test]
+    fn lone_blind_review_is_revealed_after_deadline() {
+        with_externalities(&mut new_test_ext(), || {
+            system::Module::<Test>::set_block_number(1);
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 50, 0));
+            assert_ok!(Marketplace::commit_review(Origin::signed(1), 0, commitment(1)));
+
+            Marketplace::on_initialize(6);
+            assert_noop!(Marketplace::commit_review(Origin::signed(2), 0, commitment(2)), "Too late to commit");
+            assert_ok!(Marketplace::reveal_review(Origin::signed(1), 0, (), vec![], H256::repeat_byte(1)));
+            assert!(Marketplace::blind_review(0).is_none());
+
+            // The buyer can still review openly to settle the sale
+            assert_ok!(Marketplace::review(Origin::signed(2), 0, (), vec![]));
+            assert_eq!(Balances::free_balance(1), 150);
+        });
+    }
+
+    #[test]
+    fn cannot_commit_after_open_review() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 50, 0));
+            assert_ok!(Marketplace::review(Origin::signed(1), 0, (), vec![]));
+
+            assert_noop!(
+                Marketplace::commit_review(Origin::signed(2), 0, commitment(2)),
+                "Listing is being reviewed openly"
+            );
+        });
+    }
+
+    #[test]
+    fn blind_review_deadline_must_fit() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 50, 0));
+
+            system::Module::<Test>::set_block_number(u64::max_value() - 1);
+            assert_noop!(
+                Marketplace::commit_review(Origin::signed(1), 0, commitment(1)),
+                "Blind review period too long"
+            );
+        });
+    }
+
+    #[test]
+    fn reviews_are_kept_and_can_be_rebutted() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 50, 0));
+            assert_noop!(Marketplace::review(Origin::signed(2), 0, (), b"slow!".to_vec()), "Comment too long");
+            assert_ok!(Marketplace::review(Origin::signed(2), 0, (), b"slow".to_vec()));
+            assert_ok!(Marketplace::review(Origin::signed(1), 0, (), vec![]));
+
+            // Kept after settlement
+            assert!(!<Listings<Test>>::exists(0));
+            assert_eq!(Marketplace::reviews(0).len(), 2);
+
+            assert_noop!(Marketplace::rebut(Origin::signed(3), 0, vec![]), "You have not been reviewed for this listing");
+            assert_ok!(Marketplace::rebut(Origin::signed(1), 0, b"fast".to_vec()));
+            assert_noop!(Marketplace::rebut(Origin::signed(1), 0, vec![]), "You've already rebutted this review");
+            assert_eq!(Marketplace::reviews(0)[0], Review {
+                reviewer: 2,
+                reviewee: 1,
+                feedback: (),
+                comment: b"slow".to_vec(),
+                rebuttal: Some(b"fast".to_vec()),
+            });
+        });
+    }
+
+    #[test]
+    fn blind_review_comments_are_committed_to() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 50, 0));
+            let salt = H256::repeat_byte(1);
+            let with_comment = <Test as system::Trait>::Hashing::hash_of(&((), b"good".to_vec(), salt));
+            assert_ok!(Marketplace::commit_review(Origin::signed(1), 0, with_comment));
+            assert_ok!(Marketplace::commit_review(Origin::signed(2), 0, commitment(2)));
+
+            assert_noop!(
+                Marketplace::reveal_review(Origin::signed(1), 0, (), b"bad".to_vec(), salt),
+                "Feedback does not match commitment"
+            );
+            assert_ok!(Marketplace::reveal_review(Origin::signed(1), 0, (), b"good".to_vec(), salt));
+            assert_eq!(Marketplace::reviews(0)[0].comment, b"good".to_vec());
+        });
+    }
+
+    #[test]
+    fn settled_sales_are_archived() {
+        with_externalities(&mut new_test_ext(), || {
+            system::Module::<Test>::set_block_number(4);
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 50, 0));
+            assert_ok!(Marketplace::review(Origin::signed(2), 0, (), vec![]));
+            assert_eq!(Marketplace::trade_count(1), 0);
+            assert_ok!(Marketplace::review(Origin::signed(1), 0, (), vec![]));
+
+            let sale = Sale {
+                seller: 1,
+                buyer: 2,
+                asset: 0,
+                price: 50u64,
+                settled: 4,
+                seller_feedback: Some(()),
+                buyer_feedback: Some(()),
+            };
+            assert_eq!(Marketplace::trade_history(1, 0, 10), vec![(0, sale.clone())]);
+            assert_eq!(Marketplace::trade_history(2, 0, 10), vec![(0, sale)]);
+        });
+    }
+
+    #[test]
+    fn blind_feedback_is_archived_when_revealed() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 50, 0));
+            assert_ok!(Marketplace::commit_review(Origin::signed(1), 0, commitment(1)));
+            assert_ok!(Marketplace::commit_review(Origin::signed(2), 0, commitment(2)));
+            assert_eq!(Marketplace::sale(0).unwrap().seller_feedback, None);
+
+            assert_ok!(Marketplace::reveal_review(Origin::signed(1), 0, (), vec![], H256::repeat_byte(1)));
+            assert_eq!(Marketplace::sale(0).unwrap().seller_feedback, Some(()));
+            assert_eq!(Marketplace::sale(0).unwrap().buyer_feedback, None);
+        });
+    }
+
+    #[test]
+    fn deposit_returned_on_forced_removal_without_slashing() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 123, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::force_remove_listing(Origin::ROOT, 0, 2, false));
+
+            assert_eq!(Balances::reserved_balance(1), 0);
+            assert_eq!(Balances::free_balance(1), 100);
+            assert!(!<Listings<Test>>::exists(0));
+        });
+    }
+
+    #[test]
+    fn suspended_accounts_cannot_post_or_buy() {
+        with_externalities(&mut new_test_ext(), || {
+            system::Module::<Test>::set_block_number(1);
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_noop!(Marketplace::suspend(Origin::signed(1), 2, 10), "Invalid origin");
+            assert_ok!(Marketplace::suspend(Origin::ROOT, 2, 10));
+
+            assert_noop!(Marketplace::buy(Origin::signed(2), 0, 50, 0), "Account is suspended");
+            assert_noop!(
+                Marketplace::post_listing(Origin::signed(2), 50, 456, 0, vec![], 0),
+                "Account is suspended"
+            );
+
+            assert_noop!(Marketplace::suspend(Origin::ROOT, 2, u64::max_value()), "Suspension too long");
+
+            // Suspensions expire
+            system::Module::<Test>::set_block_number(11);
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 50, 0));
+        });
+    }
+
+    #[test]
+    fn reported_listings_are_flagged_and_removed_when_upheld() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::report_listing(Origin::signed(2), 0, 9));
+            assert_noop!(Marketplace::report_listing(Origin::signed(2), 0, 9), "You've already reported this listing");
+            assert_eq!(Balances::reserved_balance(2), 5);
+            assert_eq!(Marketplace::status(0), Status::Active);
+
+            assert_ok!(Marketplace::report_listing(Origin::signed(3), 0, 9));
+            assert_eq!(Marketplace::status(0), Status::Flagged);
+            assert_eq!(Marketplace::listings_in_category(0, 0, 10), vec![]);
+            assert_noop!(Marketplace::buy(Origin::signed(2), 0, 50, 0), "Listing is flagged pending moderation");
+            assert_noop!(Marketplace::cancel_listing(Origin::signed(1), 0), "Listing is flagged pending moderation");
+
+            // Half of the seller's deposit of 10 is shared between the reporters
+            assert_ok!(Marketplace::resolve_reports(Origin::ROOT, 0, true, 9));
+            assert!(!<Listings<Test>>::exists(0));
+            assert_eq!(Balances::free_balance(2), 102);
+            assert_eq!(Balances::free_balance(3), 7);
+            assert_eq!(Balances::free_balance(1), 90);
+            assert_eq!(Balances::reserved_balance(1), 0);
+        });
+    }
+
+    #[test]
+    fn frivolous_reports_are_slashed() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::report_listing(Origin::signed(2), 0, 9));
+            assert_ok!(Marketplace::report_listing(Origin::signed(3), 0, 9));
+
+            assert_ok!(Marketplace::resolve_reports(Origin::ROOT, 0, false, 0));
+            assert_eq!(Marketplace::status(0), Status::Active);
+            assert_eq!(Marketplace::listings_in_category(0, 0, 10).len(), 1);
+            assert_eq!(Balances::free_balance(2), 95);
+            assert_eq!(Balances::reserved_balance(2), 0);
+            assert_noop!(Marketplace::resolve_reports(Origin::ROOT, 0, false, 0), "Listing has not been reported");
+        });
+    }
+
+    #[test]
+    fn verified_only_categories_reject_unverified_sellers() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::set_verified_only(Origin::ROOT, 1, true));
+            assert_noop!(
+                Marketplace::post_listing(Origin::signed(2), 50, 456, 1, vec![], 0),
+                "Only verified sellers may post in this category"
+            );
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 1, vec![], 0));
+            assert_ok!(Marketplace::post_listing(Origin::signed(2), 50, 456, 0, vec![], 0));
+
+            let verified = |c| Marketplace::listings_in_category(c, 0, 10)
+                .into_iter().map(|(_, _, verified)| verified).collect::<Vec<_>>();
+            assert_eq!(verified(1), vec![true]);
+            assert_eq!(verified(0), vec![false]);
+        });
+    }
+
+    #[test]
+    fn approved_returns_refund_the_buyer() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 50, 0));
+            assert_noop!(Marketplace::request_return(Origin::signed(3), 0), "You did not buy this listing");
+            assert_ok!(Marketplace::request_return(Origin::signed(2), 0));
+            assert_noop!(Marketplace::review(Origin::signed(1), 0, (), vec![]), "A return has been requested");
+
+            assert_noop!(Marketplace::approve_return(Origin::signed(2), 0), "Cannot approve a return of another seller's listing");
+            assert_ok!(Marketplace::approve_return(Origin::signed(1), 0));
+            assert_eq!(Balances::free_balance(2), 100);
+            assert_eq!(Balances::reserved_balance(2), 0);
+            assert_noop!(Marketplace::review(Origin::signed(2), 0, (), vec![]), "Listing is still active");
+
+            // The listing is back on sale, and the seller's deposit stays reserved
+            assert_eq!(Marketplace::status(0), Status::Active);
+            assert_eq!(Marketplace::listings_in_category(0, 0, 10).len(), 1);
+            assert_eq!(Balances::reserved_balance(1), 10);
+            assert_ok!(Marketplace::buy(Origin::signed(3), 0, 50, 0));
+        });
+    }
+
+    #[test]
+    fn returns_are_only_possible_within_window() {
+        with_externalities(&mut new_test_ext(), || {
+            system::Module::<Test>::set_block_number(1);
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 50, 0));
+
+            system::Module::<Test>::set_block_number(12);
+            assert_noop!(Marketplace::request_return(Origin::signed(2), 0), "Return window has passed");
+        });
+    }
+
+    #[test]
+    fn rejected_returns_leave_the_sale_in_place() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 50, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 50, 0));
+            assert_ok!(Marketplace::request_return(Origin::signed(2), 0));
+            assert_ok!(Marketplace::reject_return(Origin::signed(1), 0));
+
+            assert_eq!(Marketplace::status(0), Status::Sold);
+            assert_noop!(Marketplace::request_return(Origin::signed(2), 0), "Listing can no longer be returned");
+            assert_ok!(Marketplace::review(Origin::signed(1), 0, (), vec![]));
+        });
+    }
+
+    #[test]
+    fn collateral_is_locked_while_listings_need_it() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 40, 456, 0, vec![], 0));
+            assert!(!Marketplace::is_bonded(&1));
+
+            assert_ok!(Marketplace::bond_collateral(Origin::signed(1), 0, 30));
+            assert_eq!(Balances::reserved_balance(1), 40);
+            assert!(Marketplace::is_bonded(&1));
+
+            assert_ok!(Marketplace::unbond_collateral(Origin::signed(1), 0, 10));
+            assert_noop!(
+                Marketplace::unbond_collateral(Origin::signed(1), 0, 1),
+                "Collateral is still needed for active listings"
+            );
+
+            // Once the listing is gone, all of it can be unbonded
+            assert_ok!(Marketplace::cancel_listing(Origin::signed(1), 0));
+            assert_ok!(Marketplace::unbond_collateral(Origin::signed(1), 0, 20));
+            assert_eq!(Balances::reserved_balance(1), 0);
+        });
+    }
+
+    #[test]
+    fn moderators_compensate_buyers_from_collateral() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_listing(Origin::signed(1), 40, 456, 0, vec![], 0));
+            assert_ok!(Marketplace::bond_collateral(Origin::signed(1), 0, 20));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 40, 0));
+
+            assert_noop!(Marketplace::compensate_buyer(Origin::signed(2), 0, 15), "Invalid origin");
+            assert_ok!(Marketplace::compensate_buyer(Origin::ROOT, 0, 15));
+            assert_eq!(Balances::free_balance(2), 75);
+            assert_eq!(Marketplace::collateral(1, 0), 5);
+
+            // Capped at what is bonded, and the rest recorded for insurance to make up
+            assert_eq!(Marketplace::shortfall(0), None);
+            assert_ok!(Marketplace::compensate_buyer(Origin::ROOT, 0, 15));
+            assert_eq!(Balances::free_balance(2), 80);
+            assert_eq!(Marketplace::collateral(1, 0), 0);
+            assert_eq!(Marketplace::shortfall(0), Some((2, 0, 10)));
+            assert_ok!(Marketplace::compensate_buyer(Origin::ROOT, 0, 5));
+            assert_eq!(<Marketplace as CompensationShortfall<_, _, _>>::shortfall(0), Some((2, 0, 15)));
+
+            <Marketplace as CompensationShortfall<u64, u32, u64>>::reduce(0, 5);
+            assert_eq!(Marketplace::shortfall(0), Some((2, 0, 10)));
+            <Marketplace as CompensationShortfall<u64, u32, u64>>::settle(0);
+            assert_eq!(Marketplace::shortfall(0), None);
+        });
+    }
+
+    #[test]
+    fn services_are_paid_by_milestone() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_noop!(Marketplace::post_service(Origin::signed(1), vec![], 456, 0, vec![], 0), "A service needs at least one milestone");
+            assert_ok!(Marketplace::post_service(Origin::signed(1), vec![20, 30], 456, 0, vec![], 0));
+            assert_eq!(Marketplace::listing(0).unwrap().price, 50);
+            assert_noop!(Marketplace::update_listing(Origin::signed(1), 0, 40, 456), "A service's price is set by its milestones");
+
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 50, 0));
+            assert_eq!(Marketplace::status(0), Status::InProgress);
+            assert_noop!(Marketplace::review(Origin::signed(2), 0, (), vec![]), "Milestones are still outstanding");
+            assert_noop!(Marketplace::approve_milestone(Origin::signed(1), 0, 0), "You did not buy this service");
+            assert_noop!(Marketplace::approve_milestone(Origin::signed(2), 0, 2), "No such milestone");
+
+            assert_ok!(Marketplace::approve_milestone(Origin::signed(2), 0, 0));
+            assert_eq!(Balances::free_balance(1), 110);
+            assert_eq!(Marketplace::escrow(0), 30);
+            assert_noop!(Marketplace::approve_milestone(Origin::signed(2), 0, 0), "Milestone has already been settled");
+
+            assert_ok!(Marketplace::approve_milestone(Origin::signed(2), 0, 1));
+            assert_eq!(Marketplace::status(0), Status::Sold);
+
+            // Feedback is given once, after the last milestone
+            assert_ok!(Marketplace::review(Origin::signed(1), 0, (), vec![]));
+            assert_ok!(Marketplace::review(Origin::signed(2), 0, (), vec![]));
+            assert_eq!(Balances::free_balance(1), 150);
+            assert_eq!(Balances::free_balance(2), 50);
+            assert_eq!(Marketplace::sale(0).unwrap().price, 50);
+        });
+    }
+
+    #[test]
+    fn disputed_milestones_are_split_by_moderators() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_service(Origin::signed(1), vec![20, 30], 456, 0, vec![], 0));
+            assert_ok!(Marketplace::buy(Origin::signed(2), 0, 50, 0));
+            assert_ok!(Marketplace::dispute_milestone(Origin::signed(2), 0, 1));
+            assert_noop!(Marketplace::dispute_milestone(Origin::signed(2), 0, 1), "Milestone can no longer be disputed");
+
+            assert_noop!(Marketplace::resolve_milestone(Origin::signed(1), 0, 1, 10), "Invalid origin");
+            assert_noop!(Marketplace::resolve_milestone(Origin::ROOT, 0, 0, 10), "Milestone is not disputed");
+            assert_noop!(Marketplace::resolve_milestone(Origin::ROOT, 0, 1, 40), "Can't pay the seller more than the milestone");
+            assert_ok!(Marketplace::resolve_milestone(Origin::ROOT, 0, 1, 10));
+            assert_eq!(Balances::free_balance(1), 100);
+            assert_eq!(Balances::free_balance(2), 70);
+            assert_eq!(Marketplace::status(0), Status::InProgress);
+
+            assert_ok!(Marketplace::approve_milestone(Origin::signed(2), 0, 0));
+            assert_ok!(Marketplace::review(Origin::signed(1), 0, (), vec![]));
+            assert_ok!(Marketplace::review(Origin::signed(2), 0, (), vec![]));
+            assert_eq!(Balances::free_balance(1), 130);
+            assert_eq!(Balances::free_balance(2), 70);
+            // The price is what the seller was paid in the end
+            assert_eq!(Marketplace::sale(0).unwrap().price, 30);
+        });
+    }
+
+    #[test]
+    fn rentals_settle_once_returned() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_rental(Origin::signed(1), 2, 20, 456, 0, vec![], 0));
+            assert_noop!(Marketplace::buy(Origin::signed(2), 0, 2, 0), "Listing is for rent");
+            assert_noop!(Marketplace::rent(Origin::signed(2), 0, 5, 1, 0), "Listing rate exceeds max rate");
+            assert_noop!(Marketplace::rent(Origin::signed(2), 0, 11, 2, 0), "Rental period too long");
+            system::Module::<Test>::set_block_number(u64::max_value() - 1);
+            assert_noop!(Marketplace::rent(Origin::signed(2), 0, 5, 2, 0), "Rental period too long");
+
+            system::Module::<Test>::set_block_number(1);
+            assert_ok!(Marketplace::rent(Origin::signed(2), 0, 5, 2, 0));
+            assert_eq!(Marketplace::status(0), Status::Rented);
+            assert_eq!(Marketplace::return_due(0), Some(6));
+            assert_eq!(Balances::reserved_balance(2), 30);
+            assert_noop!(Marketplace::review(Origin::signed(2), 0, (), vec![]), "Rented item has not been returned");
+
+            // Still rented through the block it is due in
+            Marketplace::on_finalize(5);
+            assert_eq!(Marketplace::status(0), Status::Rented);
+            assert_noop!(Marketplace::claim_rental_deposit(Origin::signed(1), 0), "Rental is not overdue");
+            assert_noop!(Marketplace::confirm_return(Origin::signed(2), 0), "Cannot end a rental of another seller's listing");
+            assert_ok!(Marketplace::confirm_return(Origin::signed(1), 0));
+            assert_eq!(Balances::reserved_balance(2), 10);
+            Marketplace::on_finalize(6);
+            assert_eq!(Marketplace::status(0), Status::Sold);
+
+            assert_noop!(
+                Marketplace::request_return(Origin::signed(2), 0),
+                "Rented items are returned to the seller instead"
+            );
+
+            assert_ok!(Marketplace::review(Origin::signed(1), 0, (), vec![]));
+            assert_ok!(Marketplace::review(Origin::signed(2), 0, (), vec![]));
+            assert_eq!(Balances::free_balance(1), 110);
+            assert_eq!(Balances::free_balance(2), 90);
+        });
+    }
+
+    #[test]
+    fn deposit_of_overdue_rental_can_be_claimed() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_rental(Origin::signed(1), 2, 20, 456, 0, vec![], 0));
+            system::Module::<Test>::set_block_number(1);
+            assert_ok!(Marketplace::rent(Origin::signed(2), 0, 5, 2, 0));
+
+            Marketplace::on_finalize(6);
+            assert_eq!(Marketplace::status(0), Status::Overdue);
+            assert_ok!(Marketplace::claim_rental_deposit(Origin::signed(1), 0));
+            assert_eq!(Balances::free_balance(1), 110);
+            assert_eq!(Balances::reserved_balance(2), 10);
+            assert_eq!(Marketplace::status(0), Status::Sold);
+            assert_noop!(Marketplace::confirm_return(Origin::signed(1), 0), "Listing is not rented out");
+            assert_noop!(
+                Marketplace::request_return(Origin::signed(2), 0),
+                "Rented items are returned to the seller instead"
+            );
+        });
+    }
+
+    #[test]
+    fn subscriptions_are_paid_until_the_allowance_runs_out() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Marketplace::post_subscription(Origin::signed(1), 10, 5, 456, 0, vec![], 0));
+            assert_noop!(Marketplace::buy(Origin::signed(2), 0, 10, 0), "Listing is a subscription");
+
+            system::Module::<Test>::set_block_number(1);
+            assert_ok!(Marketplace::subscribe(Origin::signed(2), 0, 25, 10, 0));
+            assert_noop!(Marketplace::subscribe(Origin::signed(2), 0, 25, 10, 0), "Already subscribed");
+            assert_eq!(Balances::free_balance(1), 100);
+            assert_eq!(Balances::free_balance(2), 90);
+            assert_eq!(Marketplace::subscription(0, 2).unwrap().next_payment, 6);
+
+            Marketplace::on_initialize(6);
+            assert_eq!(Balances::free_balance(2), 80);
+
+            // Only 5 of the allowance is left
+            Marketplace::on_initialize(11);
+            assert_eq!(Balances::free_balance(2), 80);
+            assert!(Marketplace::subscription(0, 2).unwrap().paused);
+
+            system::Module::<Test>::set_block_number(12);
+            assert_ok!(Marketplace::resume_subscription(Origin::signed(2), 0, 10));
+            assert_eq!(Balances::free_balance(2), 70);
+            assert!(!Marketplace::subscription(0, 2).unwrap().paused);
+
+            assert_ok!(Marketplace::cancel_subscription(Origin::signed(2), 0));
+            Marketplace::on_initialize(17);
+            assert_eq!(Balances::free_balance(2), 70);
+            assert_eq!(Balances::free_balance(1), 120);
+        });
+    }
+
+    #[test]
+    fn subscription_payments_over_the_limit_are_carried_over() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_noop!(
+                Marketplace::post_subscription(Origin::signed(1), 0, 5, 456, 0, vec![], 0),
+                "A subscription must have a price"
+            );
+            assert_ok!(Marketplace::post_subscription(Origin::signed(1), 2, 5, 456, 0, vec![], 0));
+            assert_noop!(Marketplace::update_listing(Origin::signed(1), 0, 0, 456), "A subscription must have a price");
+
+            Balances::make_free_balance_be(&4, 100);
+            system::Module::<Test>::set_block_number(1);
+            for subscriber in 2..5 {
+                assert_ok!(Marketplace::subscribe(Origin::signed(subscriber), 0, 10, 2, 0));
+            }
+
+            // Only two payments are taken in block 6
+            Marketplace::on_initialize(6);
+            assert_eq!(Balances::free_balance(2), 96);
+            assert_eq!(Balances::free_balance(3), 1);
+            assert_eq!(Balances::free_balance(4), 98);
+
+            // The third keeps its schedule
+            Marketplace::on_initialize(7);
+            assert_eq!(Balances::free_balance(4), 96);
+            assert_eq!(Marketplace::subscription(0, 4).unwrap().next_payment, 11);
+        });
+    }
+
+    #[test]
+    fn subscription_payments_include_the_sale_fee() {
+        with_externalities(&mut new_test_ext(), || {
+            SALE_FEE.with(|v| *v.borrow_mut() = Perbill::from_percent(10));
+            Balances::make_free_balance_be(&99, 1);
+            assert_ok!(Marketplace::post_subscription(Origin::signed(1), 10, 5, 456, 0, vec![], 0));
+
+            // Nothing is paid if the next payment can't be scheduled
+            system::Module::<Test>::set_block_number(u64::max_value() - 1);
+            assert_noop!(Marketplace::subscribe(Origin::signed(2), 0, 25, 10, 0), "Subscription period too long");
+
+            // Nor if the subscriber can't afford all of it
+            system::Module::<Test>::set_block_number(1);
+            assert_noop!(
+                Marketplace::subscribe(Origin::signed(3), 0, 25, 10, 0),
+                "Insufficient balance to pay for subscription"
+            );
+
+            assert_ok!(Marketplace::subscribe(Origin::signed(2), 0, 25, 10, 0));
+            assert_eq!(Balances::free_balance(2), 90);
+            assert_eq!(Balances::reserved_balance(2), 0);
+            assert_eq!(Balances::free_balance(1), 99);
+            assert_eq!(Balances::free_balance(99), 2);
+        });
+    }
 }
@@ -0,0 +1,35 @@
+/// Versioning of module storage layouts, so that storage written by an older
+/// runtime can be migrated when the chain upgrades instead of failing to decode.
+///
+/// A module using this keeps its version in a storage value, and calls `migrate`
+/// from `on_initialize`. Whenever the layout of a storage item changes, bump
+/// `CURRENT_STORAGE_VERSION` and teach `migrate_step` to convert storage written
+/// at the previous version. New enum variants, such as a new listing `Status`,
+/// only need a migration if they are not appended after the existing ones.
+
+/// A module whose storage layout is versioned.
+pub trait VersionedStorage {
+    /// The version of the storage layout this code reads and writes.
+    const CURRENT_STORAGE_VERSION: u32;
+
+    /// The version of the storage layout currently on chain.
+    fn storage_version() -> u32;
+
+    /// Record the version of the storage layout on chain.
+    fn set_storage_version(version: u32);
+
+    /// Convert storage written at version `from`, returning the version it is now at.
+    fn migrate_step(from: u32) -> u32;
+
+    /// Bring storage up to the current version, one step at a time.
+    /// Only reads the version when there is nothing to do, so it is cheap
+    /// enough to call in every block.
+    fn migrate() {
+        let mut version = Self::storage_version();
+        while version < Self::CURRENT_STORAGE_VERSION {
+            // Always make progress, even if a step forgets to
+            version = Self::migrate_step(version).max(version + 1);
+            Self::set_storage_version(version);
+        }
+    }
+}
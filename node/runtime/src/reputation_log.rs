@@ -0,0 +1,494 @@
+/// A reputation system that keeps a log of every rating and forwards it to
+/// another reputation system, the backend. Because the log holds every rating,
+/// the backend can be swapped for a different one on a live chain and rebuilt
+/// by replaying the log, without losing anyone's history.
+///
+/// To switch backends:
+/// 1. While the old backend is still configured, `import` every account that was
+///    rated before the log existed. Accounts are also imported automatically the
+///    first time they are rated.
+/// 2. Wait for the imports to finish, then upgrade the runtime to use the new backend.
+/// 3. Call `replay` to rebuild the new backend from the log.
+///
+/// Imports are queued and logged one batch per block, so that an account with a
+/// long history doesn't make rating them expensive. Ratings given to an account
+/// while its import is queued are logged straight away, but only forwarded to the
+/// backend once the import is done.
+///
+/// Each rating is also emitted as a `Rated` event, with the block it was given in
+/// and what it was given for, so the whole feedback graph can be replayed into
+/// other reputation algorithms off-chain. The log is never pruned, because
+/// replaying into a new backend needs all of it.
+
+use rstd::prelude::*;
+use support::{ensure, decl_module, decl_storage, decl_event, StorageValue, StorageMap, dispatch::Result};
+use support::{Parameter, traits::Get};
+use system::ensure_root;
+use codec::{ Encode, Decode };
+
+use crate::reputation_trait::{ Reputation, ExportReputation, ContextualReputation };
+
+/// The module's configuration trait.
+pub trait Trait: system::Trait {
+    /// The reputation system ratings are forwarded to.
+    type Backend: ExportReputation<Self::AccountId> + ContextualReputation<Self::AccountId, Self::Context>;
+
+    /// What a rating can be given for, e.g. a marketplace listing.
+    type Context: Parameter;
+
+    /// How many logged ratings are replayed, or imported, in each block.
+    type ReplayBatch: Get<u32>;
+
+    /// The overarching event type.
+    type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+}
+
+pub type RatingIndex = u64;
+type FeedbackOf<T> = <<T as Trait>::Backend as Reputation<<T as system::Trait>::AccountId>>::Feedback;
+type ScoreOf<T> = <<T as Trait>::Backend as Reputation<<T as system::Trait>::AccountId>>::Score;
+
+type LoggedRatingOf<T> = LoggedRating<<T as system::Trait>::AccountId, <T as system::Trait>::BlockNumber, FeedbackOf<T>, <T as Trait>::Context>;
+
+/// One rating, as it was given
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct LoggedRating<AccountId, BlockNumber, Feedback, Context> {
+    // Unknown for feedback imported from before the log existed.
+    rater: Option<AccountId>,
+    ratee: AccountId,
+    feedback: Feedback,
+    // The block the rating was logged in
+    block: BlockNumber,
+    // Unknown for imported feedback, and for ratings given without one.
+    context: Option<Context>,
+}
+
+// This module's storage items.
+decl_storage! {
+    trait Store for Module<T: Trait> as ReputationLog {
+        Ratings get(rating): map RatingIndex => Option<LoggedRatingOf<T>>;
+        RatingCount get(rating_count): RatingIndex;
+        // Accounts whose reputation from before the log existed has been logged, or is queued to be.
+        Imported get(imported): map T::AccountId => bool;
+        // Accounts waiting for their earlier reputation to be logged, in order.
+        ImportQueue get(queued_import): map u64 => Option<T::AccountId>;
+        ImportsQueued get(imports_queued): u64;
+        ImportsDone get(imports_done): u64;
+        // How much of the next queued account's earlier reputation has been logged so far.
+        ImportProgress get(import_progress): u32;
+        // Ratings logged for an account while its import is queued, to forward once it is done.
+        HeldRatings get(held_ratings): map T::AccountId => Option<Vec<RatingIndex>>;
+        // The next rating to replay into the backend, while a replay is in progress.
+        ReplayCursor get(replay_cursor): Option<RatingIndex>;
+    }
+}
+
+decl_module! {
+    /// The module declaration.
+    pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+        // Initializing events
+        // this is needed only if you are using events in your module
+        fn deposit_event() = default;
+
+        /// How many logged ratings are replayed, or imported, in each block.
+        const ReplayBatch: u32 = T::ReplayBatch::get();
+
+        fn on_initialize(_n: T::BlockNumber) {
+            if let Some(cursor) = ReplayCursor::get() {
+                Self::replay_batch(cursor);
+            } else {
+                Self::import_batch();
+            }
+        }
+
+        /// Queue logging the reputation the given accounts had before the log existed,
+        /// as reported by the current backend.
+        pub fn import(origin, accounts: Vec<T::AccountId>) -> Result {
+            ensure_root(origin)?;
+            ensure!(ReplayCursor::get().is_none(), "Cannot import during a replay");
+
+            for who in accounts.iter() {
+                Self::queue_import(who);
+            }
+            Ok(())
+        }
+
+        /// Rebuild the backend by replaying every logged rating into it, one batch per block.
+        /// The backend must not hold any reputation yet, e.g. because it was just added to the runtime.
+        pub fn replay(origin) -> Result {
+            ensure_root(origin)?;
+            ensure!(ReplayCursor::get().is_none(), "Replay already in progress");
+            ensure!(ImportsDone::get() == ImportsQueued::get(), "Imports are still in progress");
+
+            ReplayCursor::put(0);
+            Self::deposit_event(RawEvent::ReplayStarted(RatingCount::get()));
+            Ok(())
+        }
+    }
+}
+
+impl<T: Trait> Module<T> {
+    /// Add a rating to the end of the log.
+    fn append(rater: Option<T::AccountId>, ratee: T::AccountId, feedback: FeedbackOf<T>, context: Option<T::Context>) {
+        let index = RatingCount::get();
+        let block = <system::Module<T>>::block_number();
+        <Ratings<T>>::insert(index, LoggedRating {
+            rater: rater.clone(),
+            ratee: ratee.clone(),
+            feedback: feedback.clone(),
+            block,
+            context: context.clone(),
+        });
+        RatingCount::put(index + 1);
+
+        Self::deposit_event(RawEvent::Rated(index, rater, ratee, feedback, context));
+    }
+
+    /// Log a rating, and forward it to the backend unless a replay or import will get to it.
+    fn log_rating(rater: T::AccountId, ratee: T::AccountId, feedback: FeedbackOf<T>, context: Option<T::Context>) -> Result {
+        // Nothing is logged that the backend would refuse
+        T::Backend::can_rate(&rater, &ratee, &feedback)?;
+        let replaying = ReplayCursor::get().is_some();
+
+        // Queue logging the ratee's earlier reputation before this rating changes it. Mid-replay
+        // the backend is incomplete, so there is nothing trustworthy to import.
+        if replaying {
+            <Imported<T>>::insert(&ratee, true);
+        } else {
+            Self::queue_import(&ratee);
+        }
+        let index = RatingCount::get();
+        Self::append(Some(rater.clone()), ratee.clone(), feedback.clone(), context.clone());
+
+        // Mid-replay, the replay will reach this rating in order
+        if replaying {
+            return Ok(());
+        }
+        // The ratee's import reads the backend, so it must not see this rating yet
+        if <HeldRatings<T>>::exists(&ratee) {
+            <HeldRatings<T>>::mutate(&ratee, |held| held.get_or_insert_with(Vec::new).push(index));
+            return Ok(());
+        }
+        match context {
+            Some(context) => T::Backend::rate_in_context(rater, ratee, feedback, context),
+            None => T::Backend::rate(rater, ratee, feedback),
+        }
+    }
+
+    /// Forward a logged rating to the backend.
+    fn forward(rating: LoggedRatingOf<T>) -> Result {
+        // Imported feedback is forwarded as if the ratee had given it to themselves
+        let rater = rating.rater.unwrap_or_else(|| rating.ratee.clone());
+        match rating.context {
+            Some(context) => T::Backend::rate_in_context(rater, rating.ratee, rating.feedback, context),
+            None => T::Backend::rate(rater, rating.ratee, rating.feedback),
+        }
+    }
+
+    /// Queue logging an account's reputation from before the log existed, unless that has already been done.
+    /// Accounts without any earlier reputation are marked imported straight away.
+    fn queue_import(who: &T::AccountId) {
+        if <Imported<T>>::get(who) {
+            return;
+        }
+        if T::Backend::export(who, 0, 1).is_empty() {
+            <Imported<T>>::insert(who, true);
+            return;
+        }
+
+        let position = ImportsQueued::get();
+        <ImportQueue<T>>::insert(position, who.clone());
+        ImportsQueued::put(position + 1);
+        <HeldRatings<T>>::insert(who, Vec::new());
+        <Imported<T>>::insert(who, true);
+    }
+
+    /// Work through the import queue, logging and forwarding up to `ReplayBatch` ratings.
+    /// Once all of an account's earlier reputation is logged, the ratings held back while
+    /// it was queued are forwarded to the backend.
+    fn import_batch() {
+        let mut budget = T::ReplayBatch::get();
+        while budget > 0 {
+            let position = ImportsDone::get();
+            let who = match <ImportQueue<T>>::get(position) {
+                Some(who) => who,
+                None => return,
+            };
+
+            let start = ImportProgress::get();
+            let feedback = T::Backend::export(&who, start, budget);
+            let count = feedback.len() as u32;
+            for f in feedback {
+                Self::append(None, who.clone(), f, None);
+            }
+
+            // There may be more of it than the budget allowed for
+            if count == budget {
+                ImportProgress::put(start + count);
+                return;
+            }
+            budget -= count;
+
+            <ImportQueue<T>>::remove(position);
+            ImportsDone::put(position + 1);
+            ImportProgress::kill();
+            let held = <HeldRatings<T>>::take(&who).unwrap_or_default();
+            budget = budget.saturating_sub(held.len() as u32);
+            for index in held {
+                if let Some(rating) = <Ratings<T>>::get(index) {
+                    let _ = Self::forward(rating);
+                }
+            }
+
+            Self::deposit_event(RawEvent::Imported(who, start + count));
+        }
+    }
+
+    /// Replay the next batch of logged ratings into the backend.
+    fn replay_batch(cursor: RatingIndex) {
+        let end = RatingCount::get().min(cursor.saturating_add(T::ReplayBatch::get() as RatingIndex));
+        for index in cursor..end {
+            if let Some(rating) = <Ratings<T>>::get(index) {
+                let _ = Self::forward(rating);
+            }
+        }
+
+        if end == RatingCount::get() {
+            ReplayCursor::kill();
+            Self::deposit_event(RawEvent::ReplayFinished(end));
+        } else {
+            ReplayCursor::put(end);
+        }
+    }
+}
+
+// Implement the reputation trait
+impl<T: Trait> Reputation<T::AccountId> for Module<T> {
+    type Score = ScoreOf<T>;
+    type Feedback = FeedbackOf<T>;
+
+    fn rate(rater: T::AccountId, ratee: T::AccountId, feedback: FeedbackOf<T>) -> Result {
+        Self::log_rating(rater, ratee, feedback, None)
+    }
+
+    fn reputation(who: T::AccountId) -> Self::Score {
+        T::Backend::reputation(who)
+    }
+
+    fn can_rate(rater: &T::AccountId, ratee: &T::AccountId, feedback: &FeedbackOf<T>) -> Result {
+        T::Backend::can_rate(rater, ratee, feedback)
+    }
+}
+
+impl<T: Trait> ContextualReputation<T::AccountId, T::Context> for Module<T> {
+    fn rate_in_context(rater: T::AccountId, ratee: T::AccountId, feedback: FeedbackOf<T>, context: T::Context) -> Result {
+        Self::log_rating(rater, ratee, feedback, Some(context))
+    }
+}
+
+decl_event!(
+    pub enum Event<T> where
+        AccountId = <T as system::Trait>::AccountId,
+        Feedback = FeedbackOf<T>,
+        Context = <T as Trait>::Context
+    {
+        // Index in the log, rater (unknown for imported feedback), ratee, feedback, context
+        Rated(RatingIndex, Option<AccountId>, AccountId, Feedback, Option<Context>),
+        // Account, number of ratings logged for their earlier reputation
+        Imported(AccountId, u32),
+        // Number of ratings to replay
+        ReplayStarted(RatingIndex),
+        // Number of ratings replayed
+        ReplayFinished(RatingIndex),
+    }
+);
+
+/// tests for this module
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simple_feedback;
+    use crate::reputation_trait::DefaultFeedback;
+
+    use runtime_io::with_externalities;
+    use primitives::{H256, Blake2Hasher};
+    use support::{impl_outer_origin, assert_ok, assert_noop, parameter_types};
+    use sr_primitives::{
+        Perbill,
+        traits::{BlakeTwo256, IdentityLookup, OnInitialize},
+        testing::Header,
+    };
+
+    impl_outer_origin! {
+        pub enum Origin for Test {}
+    }
+
+    // For testing the module, we construct most of a mock runtime. This means
+    // first constructing a configuration type (`Test`) which `impl`s each of the
+    // configuration traits of modules we want to use.
+    #[derive(Clone, Eq, PartialEq)]
+    pub struct Test;
+    parameter_types! {
+        pub const BlockHashCount: u64 = 250;
+        pub const MaximumBlockWeight: u32 = 1024;
+        pub const MaximumBlockLength: u32 = 2 * 1024;
+        pub const AvailableBlockRatio: Perbill = Perbill::one();
+    }
+    impl system::Trait for Test {
+        type Origin = Origin;
+        type Call = ();
+        type Index = u64;
+        type BlockNumber = u64;
+        type Hash = H256;
+        type Hashing = BlakeTwo256;
+        type AccountId = u64;
+        type Lookup = IdentityLookup<Self::AccountId>;
+        type Header = Header;
+        type WeightMultiplierUpdate = ();
+        type Event = ();
+        type BlockHashCount = BlockHashCount;
+        type MaximumBlockWeight = MaximumBlockWeight;
+        type MaximumBlockLength = MaximumBlockLength;
+        type AvailableBlockRatio = AvailableBlockRatio;
+        type Version = ();
+    }
+
+    impl simple_feedback::Trait for Test {
+        type Event = ();
+    }
+
+    parameter_types! {
+        pub const ReplayBatch: u32 = 2;
+    }
+    impl Trait for Test {
+        type Backend = SimpleFeedback;
+        type Context = u32;
+        type ReplayBatch = ReplayBatch;
+        type Event = ();
+    }
+    type SimpleFeedback = simple_feedback::Module<Test>;
+    type ReputationLog = Module<Test>;
+
+    // This function basically just builds a genesis storage key/value store according to
+    // our desired mockup.
+    fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+        system::GenesisConfig::default().build_storage::<Test>().unwrap().into()
+    }
+
+    #[test]
+    fn ratings_are_logged_and_forwarded() {
+        with_externalities(&mut new_test_ext(), || {
+            system::Module::<Test>::set_block_number(3);
+            assert_ok!(ReputationLog::rate(1, 2, DefaultFeedback::Positive));
+            assert_eq!(ReputationLog::rating_count(), 1);
+            assert_eq!(ReputationLog::rating(0), Some(LoggedRating {
+                rater: Some(1),
+                ratee: 2,
+                feedback: DefaultFeedback::Positive,
+                block: 3,
+                context: None,
+            }));
+
+            // The ratee had no earlier reputation to import
+            assert!(ReputationLog::imported(2));
+            assert_eq!(ReputationLog::imports_queued(), 0);
+            assert_eq!(ReputationLog::reputation(2), 1);
+        });
+    }
+
+    #[test]
+    fn earlier_reputation_is_imported_on_first_rating() {
+        with_externalities(&mut new_test_ext(), || {
+            // Ratings given straight to the backend, before the log existed
+            assert_ok!(SimpleFeedback::rate(3, 2, DefaultFeedback::Positive));
+            assert_ok!(SimpleFeedback::rate(4, 2, DefaultFeedback::Positive));
+
+            // The rating is logged, but held back until the import is done
+            assert_ok!(ReputationLog::rate(1, 2, DefaultFeedback::Negative));
+            assert_eq!(ReputationLog::rating_count(), 1);
+            assert!(ReputationLog::imported(2));
+            assert_eq!(ReputationLog::reputation(2), 2);
+            assert_noop!(ReputationLog::replay(Origin::ROOT), "Imports are still in progress");
+
+            // One batch per block
+            ReputationLog::on_initialize(1);
+            assert_eq!(ReputationLog::rating_count(), 3);
+            assert_eq!(ReputationLog::rating(1).unwrap().rater, None);
+            assert_eq!(ReputationLog::import_progress(), 2);
+            assert_eq!(ReputationLog::reputation(2), 2);
+
+            ReputationLog::on_initialize(2);
+            assert_eq!(ReputationLog::rating_count(), 3);
+            assert_eq!(ReputationLog::imports_done(), 1);
+            assert_eq!(ReputationLog::held_ratings(2), None);
+            assert_eq!(ReputationLog::reputation(2), 1);
+
+            // Importing again queues nothing
+            assert_ok!(ReputationLog::import(Origin::ROOT, vec![2]));
+            assert_eq!(ReputationLog::imports_queued(), 1);
+        });
+    }
+
+    #[test]
+    fn several_accounts_are_imported_per_block() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(SimpleFeedback::rate(3, 2, DefaultFeedback::Positive));
+            assert_ok!(SimpleFeedback::rate(3, 4, DefaultFeedback::Negative));
+            assert_ok!(SimpleFeedback::rate(3, 4, DefaultFeedback::Negative));
+
+            // Account 5 has nothing to import
+            assert_ok!(ReputationLog::import(Origin::ROOT, vec![2, 4, 5]));
+            assert_eq!(ReputationLog::imports_queued(), 2);
+            assert!(ReputationLog::imported(5));
+
+            // Account 2 is imported, and as much of account 4 as the batch allows
+            ReputationLog::on_initialize(1);
+            assert_eq!(ReputationLog::imports_done(), 1);
+            assert_eq!(ReputationLog::import_progress(), 1);
+            assert_eq!(ReputationLog::rating_count(), 2);
+
+            ReputationLog::on_initialize(2);
+            assert_eq!(ReputationLog::imports_done(), 2);
+            assert_eq!(ReputationLog::rating_count(), 3);
+            assert_ok!(ReputationLog::replay(Origin::ROOT));
+        });
+    }
+
+    #[test]
+    fn replay_rebuilds_backend_in_batches() {
+        with_externalities(&mut new_test_ext(), || {
+            // A log whose backend has been swapped for an empty one
+            let rating = |rater, ratee, feedback| LoggedRating { rater, ratee, feedback, block: 0, context: None };
+            <Ratings<Test>>::insert(0, rating(None, 2, DefaultFeedback::Positive));
+            <Ratings<Test>>::insert(1, rating(Some(1), 2, DefaultFeedback::Positive));
+            <Ratings<Test>>::insert(2, rating(Some(2), 1, DefaultFeedback::Negative));
+            RatingCount::put(3);
+
+            assert_ok!(ReputationLog::replay(Origin::ROOT));
+            ReputationLog::on_initialize(1);
+            assert_eq!(ReputationLog::replay_cursor(), Some(2));
+            assert_eq!(SimpleFeedback::reputation(2), 2);
+
+            // Ratings given mid-replay wait their turn
+            assert_ok!(ReputationLog::rate(2, 1, DefaultFeedback::Negative));
+            assert_eq!(SimpleFeedback::reputation(1), 0);
+
+            ReputationLog::on_initialize(2);
+            assert_eq!(ReputationLog::replay_cursor(), None);
+            assert_eq!(SimpleFeedback::reputation(1), -2);
+        });
+    }
+
+    #[test]
+    fn ratings_record_block_and_context() {
+        with_externalities(&mut new_test_ext(), || {
+            system::Module::<Test>::set_block_number(5);
+            assert_ok!(ReputationLog::rate_in_context(1, 2, DefaultFeedback::Positive, 7));
+
+            let rating = ReputationLog::rating(0).unwrap();
+            assert_eq!(rating.block, 5);
+            assert_eq!(rating.context, Some(7));
+            assert_eq!(SimpleFeedback::reputation(2), 1);
+        });
+    }
+}
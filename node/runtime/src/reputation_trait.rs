@@ -1,3 +1,4 @@
+use rstd::prelude::*;
 use support::dispatch::Result;
 // https://crates.parity.io/parity_codec/trait.Codec.html
 use codec::{ Encode, Decode, Codec };
@@ -16,6 +17,13 @@ pub trait Reputation<AccountId> {
     fn rate(rater: AccountId, ratee: AccountId, feedback: Self::Feedback)
       -> Result;
 
+    /// Check that `rate` would accept a rating, without giving it. If this succeeds,
+    /// so must `rate`. Systems that can't refuse a rating can keep the default.
+    fn can_rate(_rater: &AccountId, _ratee: &AccountId, _feedback: &Self::Feedback)
+      -> Result {
+        Ok(())
+    }
+
     // Create a feedback form. A feedback form must exist in order for a user to rate another user.
     // This is an idea that may be wise or unnecessary, but for now we omit it.
     //fn issue_form(rater: &AccountIt, ratee: &AccountId);
@@ -24,6 +32,25 @@ pub trait Reputation<AccountId> {
     fn reputation(who : AccountId) -> Self::Score;
 }
 
+/// A reputation system that can describe an account's reputation as feedback,
+/// so that it can be carried over into a different reputation system.
+pub trait ExportReputation<AccountId>: Reputation<AccountId> {
+    /// Feedback which, given to `who` in an empty system of the same kind,
+    /// would reproduce their current reputation. Returns at most `count` pieces,
+    /// starting from the `start`th, so that a long history can be exported in batches.
+    fn export(who: &AccountId, start: u32, count: u32) -> Vec<Self::Feedback>;
+}
+
+/// A reputation system that can be told what a rating was given for,
+/// e.g. the marketplace listing it reviews.
+pub trait ContextualReputation<AccountId, Context>: Reputation<AccountId> {
+    /// Rate as `rate` does. Systems that have no use for the context ignore it.
+    fn rate_in_context(rater: AccountId, ratee: AccountId, feedback: Self::Feedback, _context: Context)
+      -> Result {
+        Self::rate(rater, ratee, feedback)
+    }
+}
+
 // TODO why couldn't I use Codec instead of Endoce, Decode here?
 #[derive(Encode, Decode, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "std", derive(Debug))]
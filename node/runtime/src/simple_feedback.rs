@@ -2,9 +2,10 @@
 /// increases by 1 on recieving positive feedback, and decreases
 /// by 1 when receiving negative feedback
 
+use rstd::prelude::*;
 use support::{decl_module, decl_storage, decl_event, StorageMap, dispatch::Result};
 
-use crate::reputation_trait::{ Reputation, DefaultFeedback };
+use crate::reputation_trait::{ Reputation, ExportReputation, ContextualReputation, DefaultFeedback };
 
 /// The module's configuration trait.
 pub trait Trait: system::Trait {
@@ -37,13 +38,8 @@ impl<T: Trait> Reputation<T::AccountId> for Module<T> {
 
     fn rate(rater: T::AccountId, ratee: T::AccountId, feedback: DefaultFeedback) -> Result {
 
-        let delta = match feedback {
-            DefaultFeedback::Positive => 1,
-            DefaultFeedback::Negative => -1,
-        };
-
-        // TODO This is not safe against overflow
-        <Scores<T>>::mutate(&ratee, |s| *s += delta);
+        let score = Self::rated_score(&ratee, &feedback)?;
+        <Scores<T>>::insert(&ratee, score);
 
         Self::deposit_event(RawEvent::Rated(rater, ratee, feedback));
 
@@ -53,8 +49,36 @@ impl<T: Trait> Reputation<T::AccountId> for Module<T> {
     fn reputation(who: T::AccountId) -> Self::Score {
         <Scores<T>>::get(&who)
     }
+
+    fn can_rate(_rater: &T::AccountId, ratee: &T::AccountId, feedback: &DefaultFeedback) -> Result {
+        Self::rated_score(ratee, feedback).map(|_| ())
+    }
+}
+
+impl<T: Trait> Module<T> {
+    /// The ratee's score once the feedback is counted.
+    fn rated_score(ratee: &T::AccountId, feedback: &DefaultFeedback) -> rstd::result::Result<Score, &'static str> {
+        let score = <Scores<T>>::get(ratee);
+        match feedback {
+            DefaultFeedback::Positive => score.checked_add(1),
+            DefaultFeedback::Negative => score.checked_sub(1),
+        }.ok_or("Reputation score overflow")
+    }
 }
 
+impl<T: Trait> ExportReputation<T::AccountId> for Module<T> {
+    fn export(who: &T::AccountId, start: u32, count: u32) -> Vec<DefaultFeedback> {
+        // A score is the net of positive and negative feedback. Widened first,
+        // as the magnitude of the lowest score doesn't fit in an i32.
+        let score = <Scores<T>>::get(who) as i64;
+        let feedback = if score >= 0 { DefaultFeedback::Positive } else { DefaultFeedback::Negative };
+        let end = score.abs().min(start as i64 + count as i64);
+        (start as i64..end).map(|_| feedback.clone()).collect()
+    }
+}
+
+impl<T: Trait, Context> ContextualReputation<T::AccountId, Context> for Module<T> {}
+
 decl_event!(
 	pub enum Event<T> where AccountId = <T as system::Trait>::AccountId {
 		// User just submitted a rating
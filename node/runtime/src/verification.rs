@@ -0,0 +1,209 @@
+/// A registrar-style module for verifying sellers. A governing origin appoints
+/// verifiers, and each verifier can record a judgement on any account once it has
+/// checked who is behind it. Other modules see the result through the
+/// `SellerVerification` trait, which only counts judgements by current verifiers.
+
+use rstd::prelude::*;
+use support::{ensure, decl_module, decl_storage, decl_event, StorageMap, dispatch::Result};
+use support::traits::EnsureOrigin;
+use system::ensure_signed;
+use codec::{ Encode, Decode };
+
+use crate::verification_trait::SellerVerification;
+
+/// The module's configuration trait.
+pub trait Trait: system::Trait {
+    /// The origin allowed to appoint and dismiss verifiers.
+    type VerifierOrigin: EnsureOrigin<Self::Origin>;
+
+    /// The overarching event type.
+    type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+}
+
+/// What a verifier has checked about an account
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum Judgement {
+    // The person behind the account passed KYC.
+    Individual,
+    // The account belongs to a verified business.
+    Business,
+}
+
+// This module's storage items.
+decl_storage! {
+    trait Store for Module<T: Trait> as Verification {
+        Verifiers get(is_verifier): map T::AccountId => bool;
+        // Each account's judgements, at most one per verifier.
+        Judgements get(judgements): map T::AccountId => Vec<(T::AccountId, Judgement)>;
+    }
+}
+
+decl_module! {
+    /// The module declaration.
+    pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+        // Initializing events
+        // this is needed only if you are using events in your module
+        fn deposit_event() = default;
+
+        /// Allow an account to verify others.
+        pub fn add_verifier(origin, verifier: T::AccountId) -> Result {
+            T::VerifierOrigin::ensure_origin(origin)?;
+            ensure!(!<Verifiers<T>>::get(&verifier), "Already a verifier");
+
+            <Verifiers<T>>::insert(&verifier, true);
+
+            Self::deposit_event(RawEvent::VerifierAdded(verifier));
+            Ok(())
+        }
+
+        /// Stop an account from verifying others. Judgements it already gave no longer count.
+        pub fn remove_verifier(origin, verifier: T::AccountId) -> Result {
+            T::VerifierOrigin::ensure_origin(origin)?;
+            ensure!(<Verifiers<T>>::get(&verifier), "Not a verifier");
+
+            <Verifiers<T>>::remove(&verifier);
+
+            Self::deposit_event(RawEvent::VerifierRemoved(verifier));
+            Ok(())
+        }
+
+        /// Record your judgement of an account, replacing any you gave before.
+        pub fn verify(origin, who: T::AccountId, judgement: Judgement) -> Result {
+            let verifier = ensure_signed(origin)?;
+            ensure!(<Verifiers<T>>::get(&verifier), "Only verifiers can verify accounts");
+
+            <Judgements<T>>::mutate(&who, |judgements| {
+                judgements.retain(|(v, _)| *v != verifier);
+                judgements.push((verifier.clone(), judgement));
+            });
+
+            Self::deposit_event(RawEvent::Verified(verifier, who, judgement));
+            Ok(())
+        }
+
+        /// Withdraw your judgement of an account.
+        pub fn revoke(origin, who: T::AccountId) -> Result {
+            let verifier = ensure_signed(origin)?;
+
+            let mut judgements = <Judgements<T>>::get(&who);
+            let before = judgements.len();
+            judgements.retain(|(v, _)| *v != verifier);
+            ensure!(judgements.len() < before, "You have not verified this account");
+            if judgements.is_empty() {
+                <Judgements<T>>::remove(&who);
+            } else {
+                <Judgements<T>>::insert(&who, judgements);
+            }
+
+            Self::deposit_event(RawEvent::Revoked(verifier, who));
+            Ok(())
+        }
+    }
+}
+
+impl<T: Trait> SellerVerification<T::AccountId> for Module<T> {
+    fn is_verified(who: &T::AccountId) -> bool {
+        <Judgements<T>>::get(who).iter().any(|(verifier, _)| <Verifiers<T>>::get(verifier))
+    }
+}
+
+decl_event!(
+    pub enum Event<T> where AccountId = <T as system::Trait>::AccountId {
+        VerifierAdded(AccountId),
+        VerifierRemoved(AccountId),
+        // Verifier, account, judgement
+        Verified(AccountId, AccountId, Judgement),
+        // Verifier, account
+        Revoked(AccountId, AccountId),
+    }
+);
+
+/// tests for this module
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use runtime_io::with_externalities;
+    use primitives::{H256, Blake2Hasher};
+    use support::{impl_outer_origin, assert_ok, assert_noop, parameter_types};
+    use sr_primitives::{
+        Perbill,
+        traits::{BlakeTwo256, IdentityLookup},
+        testing::Header,
+    };
+
+    impl_outer_origin! {
+        pub enum Origin for Test {}
+    }
+
+    // For testing the module, we construct most of a mock runtime. This means
+    // first constructing a configuration type (`Test`) which `impl`s each of the
+    // configuration traits of modules we want to use.
+    #[derive(Clone, Eq, PartialEq)]
+    pub struct Test;
+    parameter_types! {
+        pub const BlockHashCount: u64 = 250;
+        pub const MaximumBlockWeight: u32 = 1024;
+        pub const MaximumBlockLength: u32 = 2 * 1024;
+        pub const AvailableBlockRatio: Perbill = Perbill::one();
+    }
+    impl system::Trait for Test {
+        type Origin = Origin;
+        type Call = ();
+        type Index = u64;
+        type BlockNumber = u64;
+        type Hash = H256;
+        type Hashing = BlakeTwo256;
+        type AccountId = u64;
+        type Lookup = IdentityLookup<Self::AccountId>;
+        type Header = Header;
+        type WeightMultiplierUpdate = ();
+        type Event = ();
+        type BlockHashCount = BlockHashCount;
+        type MaximumBlockWeight = MaximumBlockWeight;
+        type MaximumBlockLength = MaximumBlockLength;
+        type AvailableBlockRatio = AvailableBlockRatio;
+        type Version = ();
+    }
+
+    impl Trait for Test {
+        type VerifierOrigin = system::EnsureRoot<u64>;
+        type Event = ();
+    }
+    type Verification = Module<Test>;
+
+    // This function basically just builds a genesis storage key/value store according to
+    // our desired mockup.
+    fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+        system::GenesisConfig::default().build_storage::<Test>().unwrap().into()
+    }
+
+    #[test]
+    fn verifiers_can_verify_accounts() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_noop!(Verification::verify(Origin::signed(1), 2, Judgement::Individual), "Only verifiers can verify accounts");
+            assert_ok!(Verification::add_verifier(Origin::ROOT, 1));
+
+            assert_ok!(Verification::verify(Origin::signed(1), 2, Judgement::Individual));
+            assert_ok!(Verification::verify(Origin::signed(1), 2, Judgement::Business));
+            assert_eq!(Verification::judgements(2), vec![(1, Judgement::Business)]);
+            assert!(Verification::is_verified(&2));
+
+            assert_ok!(Verification::revoke(Origin::signed(1), 2));
+            assert!(!Verification::is_verified(&2));
+            assert_noop!(Verification::revoke(Origin::signed(1), 2), "You have not verified this account");
+        });
+    }
+
+    #[test]
+    fn judgements_of_removed_verifiers_do_not_count() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(Verification::add_verifier(Origin::ROOT, 1));
+            assert_ok!(Verification::verify(Origin::signed(1), 2, Judgement::Individual));
+
+            assert_ok!(Verification::remove_verifier(Origin::ROOT, 1));
+            assert!(!Verification::is_verified(&2));
+        });
+    }
+}
@@ -0,0 +1,13 @@
+/// Something that knows whether an account's real-world identity has been
+/// checked, e.g. that a seller has passed KYC or business verification.
+pub trait SellerVerification<AccountId> {
+    /// Whether anyone trusted to verify accounts currently vouches for `who`.
+    fn is_verified(who: &AccountId) -> bool;
+}
+
+/// No account is ever verified.
+impl<AccountId> SellerVerification<AccountId> for () {
+    fn is_verified(_who: &AccountId) -> bool {
+        false
+    }
+}
@@ -132,7 +132,11 @@ export default function ExploreListing(props) {
         api={api}
         accountPair={accountPair}
         label={"Buy"}
-        params={[listingId]}
+        params={[
+          listingId,
+          listing.isSome ? listing.unwrap().price : 0,
+          listing.isSome ? listing.unwrap().version : 0,
+        ]}
         setStatus={setStatus}
         disabled={!canBuy()}
         tx={api.tx.marketplace.buy}
@@ -150,7 +154,7 @@ export default function ExploreListing(props) {
         api={api}
         accountPair={accountPair}
         label={"Review Positively"}
-        params={[listingId, "Positive"]}
+        params={[listingId, "Positive", ""]}
         setStatus={setStatus}
         disabled={ !canReview()}
         tx={api.tx.marketplace.review}
@@ -159,7 +163,7 @@ export default function ExploreListing(props) {
         api={api}
         accountPair={accountPair}
         label={"Review Negatively"}
-        params={[listingId, "Negative"]}
+        params={[listingId, "Negative", ""]}
         setStatus={setStatus}
         disabled={ !canReview()}
         tx={api.tx.marketplace.review}
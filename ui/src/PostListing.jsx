@@ -9,9 +9,11 @@ export default function PostListing(props) {
   const initialState = {
     price: 0,
     description: 0,
+    category: 0,
+    asset: 0,
   };
   const [formState, setFormState] = useState(initialState);
-  const { price, description } = formState;
+  const { price, description, category, asset } = formState;
 
   const onChange = (_, data) => {
     setFormState(formState => {
@@ -46,12 +48,31 @@ export default function PostListing(props) {
             type="number"
           />
         </Form.Field>
+        <Form.Field>
+          <Input
+            label="Category"
+            fluid
+            onChange={onChange}
+            state="category"
+            type="number"
+          />
+        </Form.Field>
+        <Form.Field>
+          <Input
+            label="Asset"
+            fluid
+            onChange={onChange}
+            placeholder="0 for the native currency"
+            state="asset"
+            type="number"
+          />
+        </Form.Field>
         <Form.Field>
           <TxButton
             api={api}
             accountPair={accountPair}
             label={"Sell"}
-            params={[price, description]}
+            params={[price, description, category, [], asset]}
             setStatus={setStatus}
             tx={api.tx.marketplace.postListing}
           />